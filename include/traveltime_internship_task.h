@@ -0,0 +1,100 @@
+#ifndef TRAVELTIME_INTERNSHIP_TASK_H
+#define TRAVELTIME_INTERNSHIP_TASK_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit it by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Status returned by functions which write into caller-provided buffers.
+typedef enum TtStatus {
+  TT_STATUS_OK = 0,
+  // A NULL pointer, coordinates out of range or an invalid time was given, see tt_last_error
+  TT_STATUS_INVALID_ARGUMENT = 1,
+  // The results do not fit into the buffer, the required length is written and the buffer is left untouched
+  TT_STATUS_BUFFER_TOO_SMALL = 2,
+  // The library failed unexpectedly, see tt_last_error
+  TT_STATUS_INTERNAL_ERROR = 3,
+} TtStatus;
+
+// Regions compiled into an index, created by tt_index_from_json or tt_index_from_file and released by tt_index_free.
+// An index is immutable, so it can be used from several threads at once.
+typedef struct TtRegionIndex TtRegionIndex;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Creates an index from a buffer holding a JSON array of regions (in the regions file format, not NUL-terminated).
+// Returns NULL if the regions are invalid, see tt_last_error.
+//
+// # Safety
+// `json` has to point to at least `len` readable bytes.
+struct TtRegionIndex *tt_index_from_json(const uint8_t *json,
+                                         size_t len);
+
+// Creates an index from a regions file. Returns NULL if the file cannot be read or the regions are invalid, see tt_last_error.
+//
+// # Safety
+// `path` has to be a NUL-terminated string.
+struct TtRegionIndex *tt_index_from_file(const char *path);
+
+// Releases an index, NULL is ignored.
+//
+// # Safety
+// `index` has to be created by this library and not used afterwards.
+void tt_index_free(struct TtRegionIndex *index);
+
+// Number of regions in the index (0 for NULL), region indices are positions of regions in the regions JSON.
+//
+// # Safety
+// `index` has to be NULL or a live index.
+size_t tt_index_region_count(const struct TtRegionIndex *index);
+
+// Name of the region, valid as long as the index. Returns NULL if there is no such region.
+//
+// # Safety
+// `index` has to be NULL or a live index.
+const char *tt_index_region_name(const struct TtRegionIndex *index, size_t region);
+
+// Writes indices of the regions containing the point into `out_regions` (ascending) and their number into `out_count`.
+// `at_unix_seconds` may point to a time, then only regions active at that time are considered, NULL considers all regions.
+//
+// # Safety
+// `index` has to be a live index, `out_regions` has to have room for `capacity` values and `out_count` has to be writable.
+enum TtStatus tt_lookup(const struct TtRegionIndex *index,
+                        double lat,
+                        double lon,
+                        const int64_t *at_unix_seconds,
+                        size_t *out_regions,
+                        size_t capacity,
+                        size_t *out_count);
+
+// Matches a batch of points, given as arrays of `count` latitudes and longitudes. Regions of point `i` are written
+// into `out_regions` from `out_offsets[i]` to `out_offsets[i + 1]`, so `out_offsets` (which may be NULL) needs `count + 1` values.
+// The total number of matches is written into `out_total`. `at_unix_seconds` works as in tt_lookup.
+//
+// # Safety
+// `index` has to be a live index, `lats` and `lons` have to hold `count` values, `out_offsets` has to be NULL
+// or have room for `count + 1` values, `out_regions` has to have room for `capacity` values and `out_total` has to be writable.
+enum TtStatus tt_match(const struct TtRegionIndex *index,
+                       const double *lats,
+                       const double *lons,
+                       size_t count,
+                       const int64_t *at_unix_seconds,
+                       size_t *out_offsets,
+                       size_t *out_regions,
+                       size_t capacity,
+                       size_t *out_total);
+
+// Message of the last error on the calling thread (NULL if there was none), valid until the next call of this library on the thread.
+const char *tt_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* TRAVELTIME_INTERNSHIP_TASK_H */
@@ -0,0 +1,114 @@
+use geo::{BooleanOps, BoundingRect, GeodesicArea, Intersects};
+use serde::Serialize;
+
+use crate::{create_polygon_from_linestring, data_structures::{Polygon, Region}, region_union};
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Overlap {
+    pub regions: [String; 2],
+    /// Geodesic area of the part the regions share
+    pub area_km2: f64,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Gap {
+    pub exterior: Polygon,
+    /// Parts of the exterior covered by regions
+    pub holes: Vec<Polygon>,
+    pub area_km2: f64,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct RegionAnalysis {
+    pub overlaps: Vec<Overlap>,
+    /// Only present when a coverage boundary was given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gaps: Option<Vec<Gap>>,
+}
+
+/// Finds every pair of regions which overlap, together with the area they share.
+/// Regions which only touch each other (e.g. share an edge) do not overlap.
+pub fn find_overlaps(regions: &[Region]) -> Vec<Overlap> {
+    let geometries: Vec<geo::MultiPolygon> = regions.iter().map(region_union).collect();
+    let bounding_rects: Vec<Option<geo::Rect>> = geometries.iter().map(|geometry| geometry.bounding_rect()).collect();
+
+    let mut overlaps = vec![];
+    for (a, region_a) in regions.iter().enumerate() {
+        for (b, region_b) in regions.iter().enumerate().skip(a + 1) {
+            let (Some(rect_a), Some(rect_b)) = (bounding_rects[a], bounding_rects[b]) else {
+                continue;
+            };
+            if !rect_a.intersects(&rect_b) {
+                continue;
+            }
+            let area = geometries[a].intersection(&geometries[b]).geodesic_area_unsigned();
+            if area > 0.0 {
+                overlaps.push(Overlap { regions: [region_a.name.clone(), region_b.name.clone()], area_km2: area / 1_000_000.0 });
+            }
+        }
+    }
+    overlaps
+}
+
+/// Finds parts of the coverage boundary (the union of `coverage` regions) which are not covered by any of the regions.
+pub fn find_gaps(regions: &[Region], coverage: &[Region]) -> Vec<Gap> {
+    let covered = regions.iter()
+        .map(region_union)
+        .fold(geo::MultiPolygon::new(vec![]), |covered, geometry| covered.union(&geometry));
+    coverage.iter()
+        .map(region_union)
+        .fold(geo::MultiPolygon::new(vec![]), |boundary, geometry| boundary.union(&geometry))
+        .difference(&covered)
+        .into_iter()
+        .map(|gap| Gap {
+            exterior: create_polygon_from_linestring(gap.exterior()),
+            holes: gap.interiors().iter().map(create_polygon_from_linestring).collect(),
+            area_km2: gap.geodesic_area_unsigned() / 1_000_000.0,
+        })
+        .collect()
+}
+
+/// Overlaps between the regions and, if the coverage boundary is given, gaps they leave inside it.
+pub fn analyze_regions(regions: &[Region], coverage: Option<&[Region]>) -> RegionAnalysis {
+    RegionAnalysis {
+        overlaps: find_overlaps(regions),
+        gaps: coverage.map(|coverage| find_gaps(regions, coverage)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{rectangle, region};
+
+    use super::*;
+
+    fn square(name: &str, min_lon: f64, max_lon: f64) -> Region {
+        region(name, rectangle(min_lon, 0.0, max_lon, 1.0))
+    }
+
+    #[test]
+    fn test_find_overlaps() {
+        let regions = vec![square("a", 0.0, 2.0), square("b", 1.0, 3.0), square("c", 3.0, 4.0)];
+        let overlaps = find_overlaps(&regions);
+
+        // "b" and "c" only share an edge
+        assert_eq!(overlaps.len(), 1);
+        assert_eq!(overlaps[0].regions, ["a".to_owned(), "b".to_owned()]);
+        // One square degree on the equator is ~12 308 km²
+        assert!((overlaps[0].area_km2 - 12_308.0).abs() < 10.0, "{}", overlaps[0].area_km2);
+    }
+
+    #[test]
+    fn test_find_gaps() {
+        let regions = vec![square("a", 0.0, 1.0), square("b", 2.0, 3.0)];
+        let coverage = vec![square("boundary", 0.0, 3.0)];
+        let gaps = find_gaps(&regions, &coverage);
+
+        assert_eq!(gaps.len(), 1);
+        assert!(gaps[0].holes.is_empty());
+        assert!((gaps[0].area_km2 - 12_308.0).abs() < 10.0, "{}", gaps[0].area_km2);
+        assert!(gaps[0].exterior.vertices.iter().all(|vert| (1.0..=2.0).contains(&vert.longitude.val())));
+
+        assert!(find_gaps(&regions, &[square("boundary", 0.0, 1.0)]).is_empty());
+    }
+}
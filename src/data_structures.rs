@@ -1,17 +1,37 @@
 use std::error::Error;
 
-use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use chrono::{DateTime, Utc};
+use serde::{ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer};
 
-#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[derive(Deserialize, Debug, PartialEq, Clone, Default)]
 pub struct Coordinates {
     pub longitude: DecimalLon,
     pub latitude: DecimalLat,
 }
 
-#[derive(Deserialize, Debug, Clone, PartialEq)]
+// Coordinates are serialized the same way they are read: as [longitude, latitude]
+impl Serialize for Coordinates {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        (self.longitude.val(), self.latitude.val()).serialize(serializer)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct Location {
     pub name: String,
     pub coordinates: Coordinates,
+    /// Optional value carried by the location (e.g. population or order value), aggregated per region in statistics
+    #[serde(default)]
+    pub weight: Option<f64>,
+    /// Time (RFC 3339) at which the location was recorded, it only matches regions active at that time
+    #[serde(default)]
+    pub timestamp: Option<DateTime<Utc>>,
+    /// Id of the track (e.g. vehicle) the location belongs to
+    #[serde(default)]
+    pub track: Option<String>,
 }
 
 // When we serialize location, we only want to serialize the name
@@ -24,6 +44,25 @@ impl Serialize for Location {
     }
 }
 
+impl Location {
+    /// All the fields of the location, in the locations file format (serializing the location only keeps its name)
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut fields = serde_json::Map::new();
+        fields.insert("name".into(), self.name.clone().into());
+        fields.insert("coordinates".into(), serde_json::to_value(&self.coordinates).unwrap());
+        if let Some(weight) = self.weight {
+            fields.insert("weight".into(), weight.into());
+        }
+        if let Some(timestamp) = self.timestamp {
+            fields.insert("timestamp".into(), serde_json::to_value(timestamp).unwrap());
+        }
+        if let Some(track) = &self.track {
+            fields.insert("track".into(), track.clone().into());
+        }
+        serde_json::Value::Object(fields)
+    }
+}
+
 #[derive(Debug)]
 pub struct BadLatitudeError(String);
 
@@ -46,9 +85,10 @@ impl std::fmt::Display for BadLongitudeError {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Default)]
 pub struct DecimalLat(f64);
 impl DecimalLat {
+    #[allow(clippy::manual_range_contains)]
     pub fn new(lat: f64) -> Result<DecimalLat, BadLatitudeError> {
         if lat < -90.0 || lat > 90.0 {
             return Err(BadLatitudeError(lat.to_string()));
@@ -60,9 +100,10 @@ impl DecimalLat {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Default)]
 pub struct DecimalLon(f64);
 impl DecimalLon {
+    #[allow(clippy::manual_range_contains)]
     pub fn new(lon: f64) -> Result<DecimalLon, BadLongitudeError> {
         if lon < -360.0 || lon > 360.0 {
             return Err(BadLongitudeError(lon.to_string()));
@@ -75,6 +116,7 @@ impl DecimalLon {
 }
 
 impl<'de> Deserialize<'de> for DecimalLat {
+    #[allow(clippy::redundant_closure)]
     fn deserialize<D>(deserializer: D) -> Result<DecimalLat, D::Error>
     where
         D: Deserializer<'de>,
@@ -84,6 +126,7 @@ impl<'de> Deserialize<'de> for DecimalLat {
 }
 
 impl<'de> Deserialize<'de> for DecimalLon {
+    #[allow(clippy::redundant_closure)]
     fn deserialize<D>(deserializer: D) -> Result<DecimalLon, D::Error>
     where
         D: Deserializer<'de>,
@@ -92,24 +135,122 @@ impl<'de> Deserialize<'de> for DecimalLon {
     }
 }
 
-#[derive(Deserialize, Debug)]
+// Derived (de)serialization is generated as inherent functions, the trait implementations below validate holes on top of it
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(remote = "Self")]
 pub struct Region {
     pub name: String,
     #[serde(rename="coordinates")]
     pub polygons: Vec<Polygon>,
+    /// Areas cut out of the polygons they lie within, every hole has to lie within one of the polygons
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub holes: Vec<Polygon>,
+    /// Distance (in metres) by which the region is grown outward (or shrunk inward, if negative) when matching
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub buffer: Option<f64>,
+    /// Used to choose a single region for locations matching several ones in exclusive mode, the highest priority wins
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i64>,
+    /// Name of the region this one lies within
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// Start (inclusive, RFC 3339) of the time window in which the region is active
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_from: Option<DateTime<Utc>>,
+    /// End (exclusive, RFC 3339) of the time window in which the region is active
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_to: Option<DateTime<Utc>>,
+}
+
+impl<'de> Deserialize<'de> for Region {
+    fn deserialize<D>(deserializer: D) -> Result<Region, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let region = Region::deserialize(deserializer)?;
+        // A hole outside every polygon would be silently ignored when matching
+        if let Some(hole) = region.holes.iter().position(|hole| !crate::hole_within_polygons(hole, &region.polygons)) {
+            return Err(serde::de::Error::custom(format!("Hole {} of region {} does not lie within any of its polygons", hole, region.name)));
+        }
+        Ok(region)
+    }
+}
+
+impl Serialize for Region {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        Region::serialize(self, serializer)
+    }
+}
+
+impl Region {
+    /// Checks whether the region is active at the given time. Without time, the region's time window is not taken into account.
+    pub fn is_active_at(&self, time: Option<&DateTime<Utc>>) -> bool {
+        match time {
+            None => true,
+            Some(time) => self.valid_from.as_ref().is_none_or(|from| from <= time) && self.valid_to.as_ref().is_none_or(|to| time < to),
+        }
+    }
 }
 
 #[derive(Serialize, Debug)]
-pub struct MatchedResult {
+pub struct MatchedResult<L = Location> {
     pub region: String,
-    pub matched_locations: Vec<Location> 
+    pub matched_locations: Vec<L>,
+    // Locations outside every region, for which this region is the nearest one
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub nearest_locations: Vec<NearestLocation>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
+pub struct MatchedLocation {
+    pub location: Location,
+    /// Geodesic distance (in metres) from the location to the nearest boundary edge of the region it matched
+    pub distance_to_boundary: f64,
+}
+
+impl Serialize for MatchedLocation {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("MatchedLocation", 2)?;
+        state.serialize_field("name", &self.location.name)?;
+        state.serialize_field("distance_to_boundary", &self.distance_to_boundary)?;
+        state.end()
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct NearestLocation {
+    pub location: Location,
+    /// Geodesic distance (in metres) from the location to the region boundary
+    pub distance: f64,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct NearestRegion {
+    pub region: String,
+    /// Geodesic distance (in metres) from the location to the region boundary
+    pub distance: f64,
+}
+
+#[derive(Debug, PartialEq)]
 pub struct Polygon {
     pub vertices: Vec<Coordinates>,
 }
 
+impl Serialize for Polygon {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.vertices.serialize(serializer)
+    }
+}
+
 impl<'de> Deserialize<'de> for Polygon {
     fn deserialize<D>(deserializer: D) -> Result<Polygon, D::Error>
     where
@@ -145,6 +286,34 @@ mod tests {
         assert_eq!(location.coordinates.latitude.0, 25.21051562929364);
     }
 
+    #[test]
+    fn test_deserialize_weighted_location() {
+        let json = r#"{
+            "name": "location1",
+            "coordinates": [
+                54.64057937965808,
+                25.21051562929364
+            ],
+            "weight": 1250.5
+        }"#;
+        let location: Location = serde_json::from_str(json).unwrap();
+        assert_eq!(location.weight, Some(1250.5));
+    }
+
+    #[test]
+    fn test_deserialize_location_with_timestamp() {
+        let json = r#"{
+            "name": "location1",
+            "coordinates": [
+                54.64057937965808,
+                25.21051562929364
+            ],
+            "timestamp": "2024-07-01T08:30:00+02:00"
+        }"#;
+        let location: Location = serde_json::from_str(json).unwrap();
+        assert_eq!(location.timestamp, Some("2024-07-01T06:30:00Z".parse().unwrap()));
+    }
+
     #[test]
     fn test_deserialize_region() {
         let json = r#"{
@@ -208,6 +377,52 @@ mod tests {
         assert_eq!(region.polygons[1].vertices[1].longitude.val(), 21.08556244179519);
     }
 
+    #[test]
+    fn test_deserialize_region_with_holes() {
+        let json = |hole: &str| format!(r#"{{"name": "frame", "coordinates": [[[0, 0], [3, 0], [3, 3], [0, 0]]], "holes": [{}]}}"#, hole);
+        let region: Region = serde_json::from_str(&json("[[2, 0.5], [2.5, 0.5], [2.5, 1], [2, 0.5]]")).unwrap();
+        assert_eq!(region.holes.len(), 1);
+
+        let orphan = serde_json::from_str::<Region>(&json("[[0, 2], [0.5, 2], [0.5, 2.5], [0, 2]]")).unwrap_err();
+        assert!(orphan.to_string().starts_with("Hole 0 of region frame does not lie within any of its polygons"), "{}", orphan);
+    }
+
+    #[test]
+    fn test_deserialize_region_with_buffer() {
+        let json = r#"{
+            "name": "region1",
+            "buffer": -25.5,
+            "coordinates": [
+                [
+                    [21.099044587495996, 55.697364539462455],
+                    [21.13167699979246, 55.63985211052827],
+                    [21.135756051329366, 55.80067402588713],
+                    [21.099044587495996, 55.697364539462455]
+                ]
+            ]
+        }"#;
+        let region: Region = serde_json::from_str(json).unwrap();
+        assert_eq!(region.buffer, Some(-25.5));
+    }
+
+    #[test]
+    fn test_region_time_window() {
+        let json = r#"{
+            "name": "morning_zone",
+            "valid_from": "2024-07-01T06:00:00+02:00",
+            "valid_to": "2024-07-01T12:00:00+02:00",
+            "coordinates": []
+        }"#;
+        let region: Region = serde_json::from_str(json).unwrap();
+        let at = |time: &str| region.is_active_at(Some(&time.parse::<DateTime<Utc>>().unwrap()));
+
+        assert!(!at("2024-07-01T03:59:59Z"));
+        assert!(at("2024-07-01T04:00:00Z"));
+        assert!(at("2024-07-01T11:00:00+02:00"));
+        assert!(!at("2024-07-01T12:00:00+02:00"));
+        assert!(region.is_active_at(None));
+    }
+
     #[test]
     fn test_serialize_result() {
         let result = vec![
@@ -220,6 +435,7 @@ mod tests {
                             latitude: DecimalLat::new(49.24340413142335).unwrap(),
                             longitude: DecimalLon::new(19.726640710592307).unwrap(),
                         },
+                        ..Default::default()
                     },
                     Location {
                         name: "Location 2".into(),
@@ -227,8 +443,10 @@ mod tests {
                             latitude: DecimalLat::new(49.232581877359536).unwrap(),
                             longitude: DecimalLon::new(19.36788978252892).unwrap(),
                         },
+                        ..Default::default()
                     },
                 ],
+                nearest_locations: vec![],
             },
             MatchedResult {
                 region: "tatry_poland_slovakia".into(),
@@ -239,6 +457,7 @@ mod tests {
                             latitude: DecimalLat::new(49.24340413142335).unwrap(),
                             longitude: DecimalLon::new(19.726640710592307).unwrap(),
                         },
+                        ..Default::default()
                     },
                     Location {
                         name: "Location 3".into(),
@@ -246,18 +465,55 @@ mod tests {
                             latitude: DecimalLat::new(49.24476375835607).unwrap(),
                             longitude: DecimalLon::new(20.219267732042425).unwrap(),
                         },
+                        ..Default::default()
                     },
                 ],
+                nearest_locations: vec![],
             },
             MatchedResult {
                 region: "empty".to_owned(),
-                matched_locations: vec![]
+                matched_locations: vec![],
+                nearest_locations: vec![],
             }
         ];
         let json_str = serde_json::to_string(&result).unwrap();
         assert_eq!(r#"[{"region":"tatry_slovakia","matched_locations":["Location 1","Location 2"]},{"region":"tatry_poland_slovakia","matched_locations":["Location 1","Location 3"]},{"region":"empty","matched_locations":[]}]"#.to_owned(), json_str);
     }
 
+    #[test]
+    fn test_serialize_result_with_distance_to_boundary() {
+        let result = MatchedResult {
+            region: "tatry_slovakia".into(),
+            matched_locations: vec![
+                MatchedLocation {
+                    location: Location {
+                        name: "Location 1".into(),
+                        coordinates: Coordinates {
+                            latitude: DecimalLat::new(49.24340413142335).unwrap(),
+                            longitude: DecimalLon::new(19.726640710592307).unwrap(),
+                        },
+                        ..Default::default()
+                    },
+                    distance_to_boundary: 1520.5,
+                },
+            ],
+            nearest_locations: vec![],
+        };
+        let json_str = serde_json::to_string(&result).unwrap();
+        assert_eq!(r#"{"region":"tatry_slovakia","matched_locations":[{"name":"Location 1","distance_to_boundary":1520.5}]}"#.to_owned(), json_str);
+    }
+
+    #[test]
+    fn test_serialize_coordinates() {
+        let coordinates = Coordinates {
+            latitude: DecimalLat::new(54.64057937965808).unwrap(),
+            longitude: DecimalLon::new(25.21051562929364).unwrap(),
+        };
+        let json_str = serde_json::to_string(&coordinates).unwrap();
+        assert_eq!("[25.21051562929364,54.64057937965808]", json_str);
+        assert_eq!(serde_json::from_str::<Coordinates>(&json_str).unwrap(), coordinates);
+    }
+
     #[test]
     fn test_bad_latitude() {
         let json = r#"{
@@ -0,0 +1,239 @@
+use chrono::{DateTime, Utc};
+use geo::GeodesicDistance;
+
+use crate::{compile_regions, create_geopoint_from_coords, create_linestring_from_coord_vec, data_structures::{Coordinates, MatchedLocation, MatchedResult, NearestRegion, Polygon, Region}, CompiledRegion};
+
+// Edges are straight lines in longitude and latitude, the same edges containment is checked against, so a point at distance 0
+// is on the boundary. The closest point on every edge is found in a local equirectangular projection around the point,
+// the distance to it is then measured on the WGS84 ellipsoid.
+fn distance_to_ring(ring: &geo::LineString, point: &geo::Point) -> f64 {
+    let metres_per_lon = point.y().to_radians().cos();
+    ring.lines()
+        .map(|edge| {
+            // Rings of regions crossing the antimeridian go past ±180, so the edge is taken the nearest way round
+            let shift = ((edge.start.x - point.x()) / 360.0).round() * 360.0;
+            let (start_x, start_y) = ((edge.start.x - shift - point.x()) * metres_per_lon, edge.start.y - point.y());
+            let (dx, dy) = (edge.dx() * metres_per_lon, edge.dy());
+            let length = dx * dx + dy * dy;
+            let t = if length > 0.0 { (-(start_x * dx + start_y * dy) / length).clamp(0.0, 1.0) } else { 0.0 };
+            point.geodesic_distance(&geo::Point::new(edge.start.x - shift + t * edge.dx(), edge.start.y + t * edge.dy()))
+        })
+        .fold(f64::INFINITY, f64::min)
+}
+
+/// Geodesic distance (in metres) from the coordinates to the nearest edge of the polygon.
+/// Edges are straight in longitude and latitude, like when checking whether the polygon contains a point.
+pub fn distance_to_polygon_boundary(polygon: &Polygon, coordinates: &Coordinates) -> f64 {
+    distance_to_ring(&create_linestring_from_coord_vec(&polygon.vertices), &create_geopoint_from_coords(coordinates))
+}
+
+// Smallest length (in metres) of one degree of latitude on WGS84, it is used to get conservative bounds in degrees
+const MIN_METRES_PER_DEGREE: f64 = 110_574.0;
+
+impl CompiledRegion<'_> {
+    // Nearest boundary edge of any of the region's polygons or holes
+    pub(crate) fn distance_to_boundary(&self, coordinates: &Coordinates) -> f64 {
+        let point = create_geopoint_from_coords(coordinates);
+        self.geometry.iter()
+            .flat_map(|poly| std::iter::once(poly.exterior()).chain(poly.interiors()))
+            .map(|ring| distance_to_ring(ring, &point))
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    // Whether the coordinates are within the region's bounding box grown by `margin` metres.
+    // It is a cheap prefilter only, so the box is rather too big than too small.
+    fn within_grown_bounds(&self, coordinates: &Coordinates, margin: f64) -> bool {
+        let Some(bounds) = self.bounds else {
+            return false
+        };
+        let lat_margin = margin / MIN_METRES_PER_DEGREE;
+        let (min_lat, max_lat) = (bounds.min().y - lat_margin, bounds.max().y + lat_margin);
+        let lat = coordinates.latitude.val();
+        if lat < min_lat || lat > max_lat {
+            return false;
+        }
+        // Degrees of longitude get shorter towards the poles, near them every longitude has to be checked
+        let widest_parallel = max_lat.abs().max(min_lat.abs());
+        if widest_parallel >= 89.0 {
+            return true;
+        }
+        let lon_margin = lat_margin / widest_parallel.to_radians().cos();
+        let (min_lon, max_lon) = (bounds.min().x - lon_margin, bounds.max().x + lon_margin);
+        // Longitudes of regions crossing the antimeridian go past ±180, so the point is also tried a full turn east and west of itself
+        let lon = coordinates.longitude.val();
+        [lon - 360.0, lon, lon + 360.0].iter().any(|lon| (min_lon..=max_lon).contains(lon))
+    }
+
+    pub(crate) fn buffered_contains(&self, coordinates: &Coordinates, buffer: f64) -> bool {
+        if buffer >= 0.0 {
+            self.contains(coordinates)
+                || (self.within_grown_bounds(coordinates, buffer) && self.distance_to_boundary(coordinates) <= buffer)
+        } else {
+            self.contains(coordinates) && self.distance_to_boundary(coordinates) >= -buffer
+        }
+    }
+
+    // Distance to the region grown (or shrunk) by its buffer, 0 when the region covers the coordinates
+    pub(crate) fn distance(&self, coordinates: &Coordinates) -> f64 {
+        let inside = self.contains(coordinates);
+        if inside && self.region.buffer.is_none_or(|buffer| buffer >= 0.0) {
+            return 0.0;
+        }
+        // Negative inside the region
+        let signed_distance = if inside { -self.distance_to_boundary(coordinates) } else { self.distance_to_boundary(coordinates) };
+        (signed_distance - self.region.buffer.unwrap_or(0.0)).max(0.0)
+    }
+}
+
+/// Geodesic distance (in metres) from the coordinates to the nearest boundary edge of any of the region's polygons or holes.
+pub fn distance_to_region_boundary(region: &Region, coordinates: &Coordinates) -> f64 {
+    CompiledRegion::new(region).distance_to_boundary(coordinates)
+}
+
+/// Index of the region nearest to the coordinates together with the distance to it, regions with a buffer are measured grown (or shrunk) by it.
+/// Regions covering the coordinates are at distance 0, regions further than `max_distance` or not active at `time` are never returned.
+pub(crate) fn nearest_region_index(coordinates: &Coordinates, regions: &[CompiledRegion], max_distance: Option<f64>, time: Option<&DateTime<Utc>>) -> Option<(usize, f64)> {
+    regions.iter()
+        .enumerate()
+        .filter(|(_, region)| region.region.is_active_at(time))
+        .map(|(idx, region)| (idx, region.distance(coordinates)))
+        .filter(|(_, distance)| max_distance.is_none_or(|max| *distance <= max))
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+}
+
+/// Finds the region nearest to the coordinates, optionally limited to regions within `max_distance` metres.
+pub fn nearest_region(coordinates: &Coordinates, regions: &[Region], max_distance: Option<f64>) -> Option<NearestRegion> {
+    nearest_region_index(coordinates, &compile_regions(regions), max_distance, None)
+        .map(|(idx, distance)| NearestRegion { region: regions[idx].name.clone(), distance })
+}
+
+/// Checks whether the coordinates are inside the region grown outward by `buffer` metres (or shrunk inward, if `buffer` is negative).
+/// Instead of building the buffered polygon, the geodesic distance to the region boundary is compared with the buffer,
+/// which is the same as buffering with a disc of that radius on the ellipsoid, without any distortion from a projection.
+pub fn buffered_region_contains(region: &Region, coordinates: &Coordinates, buffer: f64) -> bool {
+    CompiledRegion::new(region).buffered_contains(coordinates, buffer)
+}
+
+/// Attaches to every matched location the distance to the boundary of the region it matched.
+/// `results` have to be in the same order as `regions`, as returned by [`crate::match_locations_to_regions`].
+pub fn with_distance_to_boundary(results: Vec<MatchedResult>, regions: &[Region]) -> Vec<MatchedResult<MatchedLocation>> {
+    results.into_iter().zip(regions).map(|(result, region)| {
+        let region = CompiledRegion::new(region);
+        MatchedResult {
+            region: result.region,
+            matched_locations: result.matched_locations.into_iter()
+                .map(|location| {
+                    let distance_to_boundary = region.distance_to_boundary(&location.coordinates);
+                    MatchedLocation { location, distance_to_boundary }
+                })
+                .collect(),
+            nearest_locations: result.nearest_locations,
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{data_structures::Location, test_utils::{coords, rectangle, region}};
+
+    use super::*;
+
+    fn square(name: &str, min_lon: f64, min_lat: f64, max_lon: f64, max_lat: f64) -> Region {
+        region(name, rectangle(min_lon, min_lat, max_lon, max_lat))
+    }
+
+    #[test]
+    fn test_distance_to_boundary_on_equator() {
+        let region = square("square", 0.0, 0.0, 1.0, 1.0);
+        // One degree of longitude on the equator is ~111.32 km on WGS84
+        let distance = distance_to_region_boundary(&region, &coords(2.0, 0.0));
+        assert!((distance - 111_319.5).abs() < 1.0, "{}", distance);
+    }
+
+    #[test]
+    fn test_distance_to_boundary_from_inside() {
+        let region = square("square", 0.0, 0.0, 1.0, 1.0);
+        // Edges along meridians are great circles, so the closest point is exactly on the other side of the meridian
+        let inside = distance_to_region_boundary(&region, &coords(0.9, 0.5));
+        let expected = create_geopoint_from_coords(&coords(0.9, 0.5)).geodesic_distance(&create_geopoint_from_coords(&coords(1.0, 0.5)));
+        assert!((inside - expected).abs() < 1.0, "{} != {}", inside, expected);
+    }
+
+    #[test]
+    fn test_nearest_region() {
+        let regions = vec![
+            square("west", 0.0, 0.0, 1.0, 1.0),
+            square("east", 3.0, 0.0, 4.0, 1.0),
+        ];
+        let nearest = nearest_region(&coords(2.6, 0.5), &regions, None).unwrap();
+        assert_eq!(nearest.region, "east");
+        assert!((nearest.distance - 44_527.8).abs() < 10.0, "{}", nearest.distance);
+
+        assert_eq!(nearest_region(&coords(0.5, 0.5), &regions, None).unwrap(), NearestRegion { region: "west".into(), distance: 0.0 });
+        assert_eq!(nearest_region(&coords(2.6, 0.5), &regions, Some(10_000.0)), None);
+    }
+
+    #[test]
+    fn test_with_distance_to_boundary() {
+        let regions = vec![square("square", 0.0, 0.0, 1.0, 1.0)];
+        let locations = vec![
+            Location { name: "close to border".into(), coordinates: coords(0.9995, 0.5), ..Default::default() },
+            Location { name: "centre".into(), coordinates: coords(0.5, 0.5), ..Default::default() },
+        ];
+        let results = with_distance_to_boundary(crate::match_locations_to_regions(&locations, &regions), &regions);
+
+        assert_eq!(results[0].matched_locations.len(), 2);
+        assert!(results[0].matched_locations[0].distance_to_boundary < 60.0);
+        assert!(results[0].matched_locations[1].distance_to_boundary > 55_000.0);
+    }
+
+    #[test]
+    fn test_buffered_region_contains() {
+        let region = square("square", 0.0, 0.0, 1.0, 1.0);
+        // ~55.7 m east of the eastern edge
+        let outside = coords(1.0005, 0.5);
+        // ~55.7 m west of the eastern edge
+        let inside = coords(0.9995, 0.5);
+
+        assert!(!buffered_region_contains(&region, &outside, 0.0));
+        assert!(!buffered_region_contains(&region, &outside, 50.0));
+        assert!(buffered_region_contains(&region, &outside, 60.0));
+
+        assert!(buffered_region_contains(&region, &inside, 0.0));
+        assert!(buffered_region_contains(&region, &inside, -50.0));
+        assert!(!buffered_region_contains(&region, &inside, -60.0));
+    }
+
+    #[test]
+    fn test_buffered_region_across_dateline() {
+        let region = square("dateline", 179.0, 0.0, 181.0, 1.0);
+        // ~55.7 m east of the eastern edge at 181 (-179)
+        let outside = coords(-178.9995, 0.5);
+
+        assert!(!buffered_region_contains(&region, &outside, 50.0));
+        assert!(buffered_region_contains(&region, &outside, 60.0));
+    }
+
+    #[test]
+    fn test_long_edge_is_straight_in_degrees() {
+        let region = square("north", 0.0, 50.0, 40.0, 60.0);
+        // A great circle between the northern corners would pass north of 61°, the edge matched against is the parallel at 60°
+        let north = coords(20.0, 61.0);
+        let distance = distance_to_region_boundary(&region, &north);
+
+        assert!((distance - 111_400.0).abs() < 500.0, "{}", distance);
+        assert!(!buffered_region_contains(&region, &north, 1000.0));
+    }
+
+    #[test]
+    fn test_nearest_region_with_negative_buffer() {
+        let mut region = square("square", 0.0, 0.0, 1.0, 1.0);
+        region.buffer = Some(-60.0);
+        // ~55.7 m west of the eastern edge, so outside the region shrunk by 60 m
+        let margin = coords(0.9995, 0.5);
+
+        assert!(!buffered_region_contains(&region, &margin, -60.0));
+        let nearest = nearest_region(&margin, &[region], None).unwrap();
+        assert!(nearest.distance > 4.0 && nearest.distance < 5.0, "{}", nearest.distance);
+    }
+}
@@ -0,0 +1,332 @@
+//! C API of the matcher, exported by the cdylib. The header is generated with cbindgen (see `cbindgen.toml`)
+//! into `include/traveltime_internship_task.h`. Built with the `ffi` feature.
+
+use std::{cell::RefCell, ffi::{c_char, CStr, CString}, panic::{self, AssertUnwindSafe}, ptr, slice};
+
+use chrono::{DateTime, Utc};
+
+use crate::{data_structures::{Coordinates, DecimalLat, DecimalLon}, index::RegionIndex};
+
+thread_local! {
+    // Message of the last error on this thread, returned by tt_last_error
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: impl std::fmt::Display) {
+    let message = message.to_string().replace('\0', "");
+    LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(message).ok());
+}
+
+// Panics must not unwind into the caller, they are reported through tt_last_error and the function returns `failed` instead
+fn catch_panic<T>(failed: T, call: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(call)).unwrap_or_else(|payload| {
+        let message = payload.downcast_ref::<&str>().copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown cause");
+        set_last_error(format!("Internal error! {}", message));
+        failed
+    })
+}
+
+/// Status returned by functions which write into caller-provided buffers.
+#[repr(C)]
+#[derive(Debug, PartialEq)]
+pub enum TtStatus {
+    Ok = 0,
+    /// A NULL pointer, coordinates out of range or an invalid time was given, see tt_last_error
+    InvalidArgument = 1,
+    /// The results do not fit into the buffer, the required length is written and the buffer is left untouched
+    BufferTooSmall = 2,
+    /// The library failed unexpectedly, see tt_last_error
+    InternalError = 3,
+}
+
+/// Regions compiled into an index, created by tt_index_from_json or tt_index_from_file and released by tt_index_free.
+/// An index is immutable, so it can be used from several threads at once.
+pub struct TtRegionIndex {
+    index: RegionIndex,
+    // NUL-terminated names, returned by tt_index_region_name
+    names: Vec<CString>,
+}
+
+fn new_index(index: RegionIndex) -> *mut TtRegionIndex {
+    let names = index.regions().iter().map(|region| CString::new(region.name.replace('\0', "")).unwrap()).collect();
+    Box::into_raw(Box::new(TtRegionIndex { index, names }))
+}
+
+// Slices from pointers given by the caller, which may be NULL when the length is 0
+unsafe fn input<'a, T>(data: *const T, len: usize) -> Option<&'a [T]> {
+    match (len, data.is_null()) {
+        (0, _) => Some(&[]),
+        (_, true) => None,
+        (_, false) => Some(slice::from_raw_parts(data, len)),
+    }
+}
+
+unsafe fn output<'a, T>(data: *mut T, len: usize) -> Option<&'a mut [T]> {
+    match (len, data.is_null()) {
+        (0, _) => Some(&mut []),
+        (_, true) => None,
+        (_, false) => Some(slice::from_raw_parts_mut(data, len)),
+    }
+}
+
+fn coordinates(lat: f64, lon: f64) -> Result<Coordinates, String> {
+    Ok(Coordinates {
+        latitude: DecimalLat::new(lat).map_err(|e| e.to_string())?,
+        longitude: DecimalLon::new(lon).map_err(|e| e.to_string())?,
+    })
+}
+
+unsafe fn time(at_unix_seconds: *const i64) -> Result<Option<DateTime<Utc>>, String> {
+    match at_unix_seconds.as_ref() {
+        Some(&secs) => DateTime::from_timestamp(secs, 0).map(Some).ok_or_else(|| format!("Invalid time {}", secs)),
+        None => Ok(None),
+    }
+}
+
+/// Creates an index from a buffer holding a JSON array of regions (in the regions file format, not NUL-terminated).
+/// Returns NULL if the regions are invalid, see tt_last_error.
+///
+/// # Safety
+/// `json` has to point to at least `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn tt_index_from_json(json: *const u8, len: usize) -> *mut TtRegionIndex {
+    catch_panic(ptr::null_mut(), || {
+        let Some(json) = input(json, len) else {
+            set_last_error("The regions buffer is NULL");
+            return ptr::null_mut()
+        };
+        match RegionIndex::from_json(json) {
+            Ok(index) => new_index(index),
+            Err(e) => {
+                set_last_error(e.to_string());
+                ptr::null_mut()
+            },
+        }
+    })
+}
+
+/// Creates an index from a regions file. Returns NULL if the file cannot be read or the regions are invalid, see tt_last_error.
+///
+/// # Safety
+/// `path` has to be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn tt_index_from_file(path: *const c_char) -> *mut TtRegionIndex {
+    catch_panic(ptr::null_mut(), || {
+        if path.is_null() {
+            set_last_error("The regions file path is NULL");
+            return ptr::null_mut()
+        }
+        let path = CStr::from_ptr(path).to_string_lossy().into_owned();
+        let index = std::fs::read(&path)
+            .map_err(|e| format!("An error occurred while reading the regions file {}! os: {}", path, e))
+            .and_then(|json| RegionIndex::from_json(&json)
+                .map_err(|e| format!("An error occurred while reading the regions file {}! {}", path, e)));
+        match index {
+            Ok(index) => new_index(index),
+            Err(e) => {
+                set_last_error(e);
+                ptr::null_mut()
+            },
+        }
+    })
+}
+
+/// Releases an index, NULL is ignored.
+///
+/// # Safety
+/// `index` has to be created by this library and not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn tt_index_free(index: *mut TtRegionIndex) {
+    catch_panic((), || {
+        if !index.is_null() {
+            drop(Box::from_raw(index));
+        }
+    })
+}
+
+/// Number of regions in the index (0 for NULL), region indices are positions of regions in the regions JSON.
+///
+/// # Safety
+/// `index` has to be NULL or a live index.
+#[no_mangle]
+pub unsafe extern "C" fn tt_index_region_count(index: *const TtRegionIndex) -> usize {
+    catch_panic(0, || {
+        index.as_ref().map_or(0, |index| index.names.len())
+    })
+}
+
+/// Name of the region, valid as long as the index. Returns NULL if there is no such region.
+///
+/// # Safety
+/// `index` has to be NULL or a live index.
+#[no_mangle]
+pub unsafe extern "C" fn tt_index_region_name(index: *const TtRegionIndex, region: usize) -> *const c_char {
+    catch_panic(ptr::null(), || {
+        index.as_ref()
+            .and_then(|index| index.names.get(region))
+            .map_or(ptr::null(), |name| name.as_ptr())
+    })
+}
+
+/// Writes indices of the regions containing the point into `out_regions` (ascending) and their number into `out_count`.
+/// `at_unix_seconds` may point to a time, then only regions active at that time are considered, NULL considers all regions.
+///
+/// # Safety
+/// `index` has to be a live index, `out_regions` has to have room for `capacity` values and `out_count` has to be writable.
+#[no_mangle]
+pub unsafe extern "C" fn tt_lookup(
+    index: *const TtRegionIndex,
+    lat: f64,
+    lon: f64,
+    at_unix_seconds: *const i64,
+    out_regions: *mut usize,
+    capacity: usize,
+    out_count: *mut usize,
+) -> TtStatus {
+    catch_panic(TtStatus::InternalError, || {
+        tt_match(index, &lat, &lon, 1, at_unix_seconds, ptr::null_mut(), out_regions, capacity, out_count)
+    })
+}
+
+/// Matches a batch of points, given as arrays of `count` latitudes and longitudes. Regions of point `i` are written
+/// into `out_regions` from `out_offsets[i]` to `out_offsets[i + 1]`, so `out_offsets` (which may be NULL) needs `count + 1` values.
+/// The total number of matches is written into `out_total`. `at_unix_seconds` works as in tt_lookup.
+///
+/// # Safety
+/// `index` has to be a live index, `lats` and `lons` have to hold `count` values, `out_offsets` has to be NULL
+/// or have room for `count + 1` values, `out_regions` has to have room for `capacity` values and `out_total` has to be writable.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn tt_match(
+    index: *const TtRegionIndex,
+    lats: *const f64,
+    lons: *const f64,
+    count: usize,
+    at_unix_seconds: *const i64,
+    out_offsets: *mut usize,
+    out_regions: *mut usize,
+    capacity: usize,
+    out_total: *mut usize,
+) -> TtStatus {
+    catch_panic(TtStatus::InternalError, || {
+        let (Some(index), Some(lats), Some(lons), Some(out_total)) = (index.as_ref(), input(lats, count), input(lons, count), out_total.as_mut()) else {
+            set_last_error("The index, coordinates or total are NULL");
+            return TtStatus::InvalidArgument
+        };
+        let time = match time(at_unix_seconds) {
+            Ok(time) => time,
+            Err(e) => {
+                set_last_error(e);
+                return TtStatus::InvalidArgument
+            },
+        };
+        let mut found = Vec::with_capacity(count);
+        for (idx, (&lat, &lon)) in lats.iter().zip(lons).enumerate() {
+            match coordinates(lat, lon) {
+                Ok(point) => found.push(index.index.lookup_indices(&point, time.as_ref())),
+                Err(e) => {
+                    set_last_error(format!("Point {}: {}", idx, e));
+                    return TtStatus::InvalidArgument
+                },
+            }
+        }
+
+        *out_total = found.iter().map(Vec::len).sum();
+        if *out_total > capacity {
+            return TtStatus::BufferTooSmall
+        }
+        let Some(regions) = output(out_regions, capacity) else {
+            set_last_error("The regions buffer is NULL");
+            return TtStatus::InvalidArgument
+        };
+        if let Some(offsets) = output(out_offsets, count + 1) {
+            let mut offset = 0;
+            offsets[0] = 0;
+            for (point_regions, next) in found.iter().zip(&mut offsets[1..]) {
+                offset += point_regions.len();
+                *next = offset;
+            }
+        }
+        for (slot, region) in regions.iter_mut().zip(found.into_iter().flatten()) {
+            *slot = region;
+        }
+        TtStatus::Ok
+    })
+}
+
+/// Message of the last error on the calling thread (NULL if there was none), valid until the next call of this library on the thread.
+#[no_mangle]
+pub extern "C" fn tt_last_error() -> *const c_char {
+    catch_panic(ptr::null(), || {
+        LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REGIONS: &str = r#"[
+        {"name": "big", "coordinates": [[[0, 0], [2, 0], [2, 2], [0, 2], [0, 0]]]},
+        {"name": "small", "coordinates": [[[0, 0], [1, 0], [1, 1], [0, 1], [0, 0]]], "valid_to": "2024-07-01T00:00:00Z"}
+    ]"#;
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(tt_last_error()).to_string_lossy().into_owned() }
+    }
+
+    #[test]
+    fn test_lookup() {
+        unsafe {
+            let index = tt_index_from_json(REGIONS.as_ptr(), REGIONS.len());
+            assert_eq!(tt_index_region_count(index), 2);
+            assert_eq!(CStr::from_ptr(tt_index_region_name(index, 1)).to_str().unwrap(), "small");
+            assert!(tt_index_region_name(index, 2).is_null());
+
+            let (mut regions, mut count) = ([0; 2], 0);
+            assert_eq!(tt_lookup(index, 0.5, 0.5, ptr::null(), regions.as_mut_ptr(), 2, &mut count), TtStatus::Ok);
+            assert_eq!(regions[..count], [0, 1]);
+            // 2024-07-01T00:00:00Z, when the small region stops being valid
+            assert_eq!(tt_lookup(index, 0.5, 0.5, &1_719_792_000, regions.as_mut_ptr(), 2, &mut count), TtStatus::Ok);
+            assert_eq!(regions[..count], [0]);
+            assert_eq!(tt_lookup(index, 95.0, 0.5, ptr::null(), regions.as_mut_ptr(), 2, &mut count), TtStatus::InvalidArgument);
+            assert!(last_error().starts_with("Point 0:"));
+            tt_index_free(index);
+        }
+    }
+
+    #[test]
+    fn test_match_batch() {
+        unsafe {
+            let index = tt_index_from_json(REGIONS.as_ptr(), REGIONS.len());
+            let (lats, lons) = ([0.5, 1.5, 3.0], [0.5, 1.5, 3.0]);
+            let (mut offsets, mut regions, mut total) = ([0; 4], [0; 3], 0);
+
+            let status = tt_match(index, lats.as_ptr(), lons.as_ptr(), 3, ptr::null(), offsets.as_mut_ptr(), regions.as_mut_ptr(), 2, &mut total);
+            assert_eq!((status, total), (TtStatus::BufferTooSmall, 3));
+            let status = tt_match(index, lats.as_ptr(), lons.as_ptr(), 3, ptr::null(), offsets.as_mut_ptr(), regions.as_mut_ptr(), 3, &mut total);
+            assert_eq!(status, TtStatus::Ok);
+            assert_eq!((offsets, regions), ([0, 2, 3, 3], [0, 1, 0]));
+            tt_index_free(index);
+        }
+    }
+
+    #[test]
+    fn test_invalid_regions() {
+        let open = r#"[{"name": "open", "coordinates": [[[0, 0], [1, 0], [1, 1], [0, 1]]]}]"#;
+        unsafe {
+            assert!(tt_index_from_json(open.as_ptr(), open.len()).is_null());
+            assert!(last_error().starts_with("Invalid regions! Polygon must be closed"), "{}", last_error());
+            assert!(tt_index_from_file(c"/nonexistent/regions.json".as_ptr()).is_null());
+            assert!(last_error().contains("/nonexistent/regions.json"));
+        }
+    }
+
+    #[test]
+    fn test_panic_is_reported() {
+        assert_eq!(catch_panic(TtStatus::InternalError, || panic!("Index out of range")), TtStatus::InternalError);
+        assert_eq!(last_error(), "Internal error! Index out of range");
+    }
+}
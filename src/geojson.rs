@@ -0,0 +1,166 @@
+use serde_json::{json, Map, Value};
+
+use crate::{create_geo_polygons, create_polygon_from_linestring, data_structures::{Location, Region}};
+
+fn feature_collection(features: Vec<Value>) -> Value {
+    json!({ "type": "FeatureCollection", "features": features })
+}
+
+fn features(value: Value) -> Result<Vec<Value>, String> {
+    match value {
+        Value::Object(mut collection) if collection.get("type") == Some(&json!("FeatureCollection")) => match collection.remove("features") {
+            Some(Value::Array(features)) => Ok(features),
+            _ => Err("The feature collection has no features array".into()),
+        },
+        _ => Err("Expected a GeoJSON FeatureCollection".into()),
+    }
+}
+
+// Splits a feature into its geometry type, coordinates and properties
+fn feature_parts(idx: usize, feature: Value) -> Result<(String, Value, Map<String, Value>), String> {
+    let Value::Object(mut feature) = feature else {
+        return Err(format!("Feature {} is not an object", idx));
+    };
+    let properties = match feature.remove("properties") {
+        Some(Value::Object(properties)) => properties,
+        _ => Map::new(),
+    };
+    let Some(Value::Object(mut geometry)) = feature.remove("geometry") else {
+        return Err(format!("Feature {} has no geometry", idx));
+    };
+    let (Some(Value::String(kind)), Some(coordinates)) = (geometry.remove("type"), geometry.remove("coordinates")) else {
+        return Err(format!("Feature {} has an invalid geometry", idx));
+    };
+    Ok((kind, coordinates, properties))
+}
+
+/// Every region becomes a MultiPolygon feature (holes are placed in the polygons they lie within),
+/// all the other fields of the region become its properties.
+pub fn regions_to_geojson(regions: &[Region]) -> Value {
+    feature_collection(regions.iter()
+        .map(|region| {
+            let coordinates: Vec<Vec<Value>> = create_geo_polygons(region).iter()
+                .map(|poly| std::iter::once(poly.exterior()).chain(poly.interiors())
+                    .map(|ring| serde_json::to_value(create_polygon_from_linestring(ring)).unwrap())
+                    .collect())
+                .collect();
+            let mut properties = serde_json::to_value(region).unwrap();
+            if let Value::Object(properties) = &mut properties {
+                properties.remove("coordinates");
+                properties.remove("holes");
+            }
+            json!({
+                "type": "Feature",
+                "geometry": { "type": "MultiPolygon", "coordinates": coordinates },
+                "properties": properties,
+            })
+        })
+        .collect())
+}
+
+/// Reads regions from Polygon and MultiPolygon features, exterior rings become the region's polygons and interior rings its holes.
+/// Properties are read like the fields of a region (`name` is required).
+pub fn regions_from_geojson(value: Value) -> Result<Vec<Region>, String> {
+    features(value)?.into_iter().enumerate()
+        .map(|(idx, feature)| {
+            let (kind, coordinates, mut properties) = feature_parts(idx, feature)?;
+            let polygons: Vec<Vec<Value>> = match kind.as_str() {
+                "Polygon" => serde_json::from_value(coordinates).map(|rings| vec![rings]),
+                "MultiPolygon" => serde_json::from_value(coordinates),
+                _ => return Err(format!("Feature {} is a {}, regions have to be polygons", idx, kind)),
+            }.map_err(|e| format!("Feature {} has invalid coordinates: {}", idx, e))?;
+
+            let (mut exteriors, mut holes) = (vec![], vec![]);
+            for rings in polygons {
+                let mut rings = rings.into_iter();
+                exteriors.extend(rings.next());
+                holes.extend(rings);
+            }
+            properties.insert("coordinates".into(), Value::Array(exteriors));
+            properties.insert("holes".into(), Value::Array(holes));
+            serde_json::from_value(Value::Object(properties)).map_err(|e| format!("Feature {} is not a valid region: {}", idx, e))
+        })
+        .collect()
+}
+
+/// Every location becomes a Point feature, with its name, weight, timestamp and track as properties.
+pub fn locations_to_geojson(locations: &[Location]) -> Value {
+    feature_collection(locations.iter()
+        .map(|loc| {
+            let mut properties = loc.to_json();
+            if let Value::Object(properties) = &mut properties {
+                properties.remove("coordinates");
+            }
+            json!({
+                "type": "Feature",
+                "geometry": { "type": "Point", "coordinates": loc.coordinates },
+                "properties": properties,
+            })
+        })
+        .collect())
+}
+
+/// Reads locations from Point features, properties are read like the fields of a location (`name` is required).
+pub fn locations_from_geojson(value: Value) -> Result<Vec<Location>, String> {
+    features(value)?.into_iter().enumerate()
+        .map(|(idx, feature)| {
+            let (kind, coordinates, mut properties) = feature_parts(idx, feature)?;
+            if kind != "Point" {
+                return Err(format!("Feature {} is a {}, locations have to be points", idx, kind));
+            }
+            properties.insert("coordinates".into(), coordinates);
+            serde_json::from_value(Value::Object(properties)).map_err(|e| format!("Feature {} is not a valid location: {}", idx, e))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{match_locations_to_regions, test_utils::square};
+
+    use super::*;
+
+    #[test]
+    fn test_regions_round_trip() {
+        let regions = vec![
+            Region { name: "frame".into(), polygons: vec![square(0.0, 3.0), square(5.0, 6.0)], holes: vec![square(1.0, 2.0)], priority: Some(2), ..Default::default() },
+        ];
+        let geojson = regions_to_geojson(&regions);
+
+        assert_eq!(geojson["features"][0]["properties"], json!({ "name": "frame", "priority": 2 }));
+        assert_eq!(geojson["features"][0]["geometry"]["coordinates"].as_array().unwrap().len(), 2);
+
+        let read_back = regions_from_geojson(geojson).unwrap();
+        assert_eq!(read_back[0].name, "frame");
+        assert_eq!(read_back[0].polygons, regions[0].polygons);
+        assert_eq!(read_back[0].holes, regions[0].holes);
+        assert_eq!(read_back[0].priority, Some(2));
+    }
+
+    #[test]
+    fn test_locations_from_geojson() {
+        let geojson = json!({
+            "type": "FeatureCollection",
+            "features": [
+                { "type": "Feature", "geometry": { "type": "Point", "coordinates": [1.5, 1.5] }, "properties": { "name": "in hole", "weight": 2.0 } },
+                { "type": "Feature", "geometry": { "type": "Point", "coordinates": [0.5, 0.5] }, "properties": { "name": "in frame" } },
+            ]
+        });
+        let locations = locations_from_geojson(geojson).unwrap();
+        assert_eq!(locations[0].weight, Some(2.0));
+        assert_eq!(locations_from_geojson(locations_to_geojson(&locations)).unwrap(), locations);
+
+        let regions = vec![Region { name: "frame".into(), polygons: vec![square(0.0, 3.0)], holes: vec![square(1.0, 2.0)], ..Default::default() }];
+        assert_eq!(match_locations_to_regions(&locations, &regions)[0].matched_locations, vec![locations[1].clone()]);
+    }
+
+    #[test]
+    fn test_invalid_geojson() {
+        assert!(regions_from_geojson(json!([])).is_err());
+        let line = json!({
+            "type": "FeatureCollection",
+            "features": [{ "type": "Feature", "geometry": { "type": "LineString", "coordinates": [[0, 0], [1, 1]] }, "properties": { "name": "line" } }]
+        });
+        assert_eq!(regions_from_geojson(line).unwrap_err(), "Feature 0 is a LineString, regions have to be polygons");
+    }
+}
@@ -0,0 +1,109 @@
+use geo::{coord, BoundingRect, Centroid, GeodesicArea, MapCoords};
+use serde::Serialize;
+
+use crate::{data_structures::{Coordinates, DecimalLat, DecimalLon, Region}, region_union, unwrap_longitude, wrap_longitude};
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct BoundingBox {
+    pub min: Coordinates,
+    pub max: Coordinates,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct RegionProperties {
+    pub region: String,
+    /// Geodesic area, without holes
+    pub area_km2: f64,
+    /// Geodesic length of all the outlines, including holes
+    pub perimeter_km: f64,
+    /// Area-weighted centroid in longitude/latitude, none for a region without area
+    pub centroid: Option<Coordinates>,
+    pub bounding_box: Option<BoundingBox>,
+}
+
+fn coordinates_from_coord(coord: geo::Coord) -> Coordinates {
+    Coordinates {
+        longitude: DecimalLon::new(coord.x).expect("Centroids and bounding boxes lie within the region's longitudes"),
+        latitude: DecimalLat::new(coord.y).expect("Centroids and bounding boxes lie within the region's latitudes"),
+    }
+}
+
+// Longitudes are unwrapped relative to the first vertex, so parts of the region on both sides of the antimeridian stay together
+fn centroid(geometry: &geo::MultiPolygon) -> Option<Coordinates> {
+    let reference = geometry.iter().next()?.exterior().0.first()?.x;
+    let unwrapped = geometry.map_coords(|vert| coord! { x: unwrap_longitude(vert.x, reference), y: vert.y });
+    unwrapped.centroid().map(|centroid| coordinates_from_coord(coord! { x: wrap_longitude(centroid.x()), y: centroid.y() }))
+}
+
+/// Geometric properties of the region, overlapping polygons of the region are merged first so shared parts count once.
+pub fn region_properties(region: &Region) -> RegionProperties {
+    let geometry = region_union(region);
+    RegionProperties {
+        region: region.name.clone(),
+        area_km2: geometry.geodesic_area_unsigned() / 1_000_000.0,
+        perimeter_km: geometry.geodesic_perimeter() / 1_000.0,
+        centroid: centroid(&geometry),
+        bounding_box: geometry.bounding_rect().map(|rect| BoundingBox {
+            min: coordinates_from_coord(rect.min()),
+            max: coordinates_from_coord(rect.max()),
+        }),
+    }
+}
+
+pub fn describe_regions(regions: &[Region]) -> Vec<RegionProperties> {
+    regions.iter().map(region_properties).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{coords, rectangle, square};
+
+    use super::*;
+
+    #[test]
+    fn test_region_properties() {
+        let region = Region { name: "square".into(), polygons: vec![square(0.0, 1.0)], ..Default::default() };
+        let properties = region_properties(&region);
+
+        // One square degree on the equator is ~12 308 km², its sides are ~110.6 km (meridians) and ~111.3 km (parallels) long
+        assert!((properties.area_km2 - 12_308.0).abs() < 10.0, "{}", properties.area_km2);
+        assert!((properties.perimeter_km - 443.8).abs() < 1.0, "{}", properties.perimeter_km);
+        assert_eq!(properties.centroid, Some(coords(0.5, 0.5)));
+        assert_eq!(properties.bounding_box, Some(BoundingBox { min: coords(0.0, 0.0), max: coords(1.0, 1.0) }));
+    }
+
+    #[test]
+    fn test_region_with_hole() {
+        let region = Region {
+            name: "frame".into(),
+            polygons: vec![square(0.0, 3.0)],
+            holes: vec![square(1.0, 2.0)],
+            ..Default::default()
+        };
+        let properties = region_properties(&region);
+        let outer = region_properties(&Region { name: "outer".into(), polygons: vec![square(0.0, 3.0)], ..Default::default() });
+
+        assert!((outer.area_km2 - properties.area_km2 - 12_300.0).abs() < 50.0);
+        assert!(properties.perimeter_km > outer.perimeter_km);
+        assert_eq!(properties.bounding_box, outer.bounding_box);
+    }
+
+    #[test]
+    fn test_empty_region() {
+        let properties = region_properties(&Region { name: "empty".into(), ..Default::default() });
+
+        assert_eq!(properties.area_km2, 0.0);
+        assert_eq!(properties.centroid, None);
+        assert_eq!(properties.bounding_box, None);
+    }
+
+    #[test]
+    fn test_centroid_across_dateline() {
+        let region = Region { name: "dateline".into(), polygons: vec![rectangle(179.0, 0.0, 180.0, 1.0), rectangle(-180.0, 0.0, -178.0, 1.0)], ..Default::default() };
+        let centroid = region_properties(&region).centroid.unwrap();
+
+        // Two thirds of the area lie east of the antimeridian
+        assert!((centroid.longitude.val() + 179.5).abs() < 1e-9, "{:?}", centroid);
+        assert!((centroid.latitude.val() - 0.5).abs() < 1e-9, "{:?}", centroid);
+    }
+}
@@ -0,0 +1,190 @@
+use std::{collections::HashMap, error::Error};
+
+use geo::Relate;
+use serde::Serialize;
+
+use crate::{data_structures::{Location, Region}, CompiledRegion};
+
+#[derive(Debug, PartialEq)]
+pub enum HierarchyError {
+    DuplicateRegion(String),
+    UnknownParent { region: String, parent: String },
+    Cycle(String),
+    NotContained { region: String, parent: String },
+}
+
+impl Error for HierarchyError {}
+
+impl std::fmt::Display for HierarchyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            HierarchyError::DuplicateRegion(region) => write!(f, "Region {} is defined more than once", region),
+            HierarchyError::UnknownParent { region, parent } => write!(f, "Parent {} of region {} does not exist", parent, region),
+            HierarchyError::Cycle(region) => write!(f, "Region {} is its own ancestor", region),
+            HierarchyError::NotContained { region, parent } => write!(f, "Region {} is not contained in its parent {}", region, parent),
+        }
+    }
+}
+
+/// Checks the hierarchy of regions whenever they declare parents, like [`RegionHierarchy::new`].
+pub fn check_hierarchy(regions: &[Region]) -> Result<(), HierarchyError> {
+    if regions.iter().any(|region| region.parent.is_some()) {
+        RegionHierarchy::new(regions)?;
+    }
+    Ok(())
+}
+
+/// Locations matched against a region hierarchy, with every match reported as a path from the root region down.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct LocationPaths {
+    pub location: Location,
+    pub paths: Vec<Vec<String>>,
+}
+
+/// Regions organized in a tree (or rather forest) by their `parent`.
+/// Children of a region are only checked for locations which matched the region itself.
+#[derive(Debug)]
+pub struct RegionHierarchy<'a> {
+    regions: Vec<CompiledRegion<'a>>,
+    roots: Vec<usize>,
+    children: Vec<Vec<usize>>,
+}
+
+impl<'a> RegionHierarchy<'a> {
+    /// Builds the hierarchy, checking that every parent exists, there are no cycles and every region lies within its parent.
+    pub fn new(regions: &'a [Region]) -> Result<RegionHierarchy<'a>, HierarchyError> {
+        let mut indices: HashMap<&str, usize> = HashMap::new();
+        for (idx, region) in regions.iter().enumerate() {
+            if indices.insert(&region.name, idx).is_some() {
+                return Err(HierarchyError::DuplicateRegion(region.name.clone()));
+            }
+        }
+
+        let mut roots = vec![];
+        let mut children = vec![vec![]; regions.len()];
+        for (idx, region) in regions.iter().enumerate() {
+            match &region.parent {
+                None => roots.push(idx),
+                Some(parent) => {
+                    let parent_idx = *indices.get(parent.as_str())
+                        .ok_or_else(|| HierarchyError::UnknownParent { region: region.name.clone(), parent: parent.clone() })?;
+                    children[parent_idx].push(idx);
+                }
+            }
+        }
+
+        // Every region reachable from the roots is visited exactly once, the rest must be part of a cycle
+        let mut reachable = vec![false; regions.len()];
+        let mut stack = roots.clone();
+        while let Some(idx) = stack.pop() {
+            reachable[idx] = true;
+            stack.extend(&children[idx]);
+        }
+        if let Some(idx) = reachable.iter().position(|visited| !visited) {
+            return Err(HierarchyError::Cycle(regions[idx].name.clone()));
+        }
+
+        let regions: Vec<CompiledRegion> = regions.iter().map(CompiledRegion::new).collect();
+        for (parent_idx, region_children) in children.iter().enumerate() {
+            let parent = &regions[parent_idx];
+            for idx in region_children {
+                if !parent.geometry.relate(regions[*idx].geometry.as_ref()).is_covers() {
+                    return Err(HierarchyError::NotContained { region: regions[*idx].region.name.clone(), parent: parent.region.name.clone() });
+                }
+            }
+        }
+
+        Ok(RegionHierarchy { regions, roots, children })
+    }
+
+    /// Paths (as region indices, from the root down) of all the deepest regions matching the location.
+    pub fn paths(&self, location: &Location) -> Vec<Vec<usize>> {
+        let mut paths = vec![];
+        self.roots.iter()
+            .for_each(|root| self.collect_paths(*root, location, &mut vec![], &mut paths));
+        paths
+    }
+
+    fn collect_paths(&self, idx: usize, location: &Location, path: &mut Vec<usize>, paths: &mut Vec<Vec<usize>>) {
+        if !self.regions[idx].matches(location) {
+            return;
+        }
+        path.push(idx);
+        let found = paths.len();
+        self.children[idx].iter()
+            .for_each(|child| self.collect_paths(*child, location, path, paths));
+        // None of the children matched, so this region is the deepest one on the path
+        if paths.len() == found {
+            paths.push(path.clone());
+        }
+        path.pop();
+    }
+
+    /// Matches every location against the hierarchy, reporting region names on the paths.
+    pub fn match_locations(&self, locations: &[Location]) -> Vec<LocationPaths> {
+        locations.iter().map(
+            |loc|
+            LocationPaths {
+                location: loc.clone(),
+                paths: self.paths(loc).into_iter()
+                    .map(|path| path.into_iter().map(|idx| self.regions[idx].region.name.clone()).collect())
+                    .collect(),
+            }
+        ).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{coords, rectangle, region};
+
+    use super::*;
+
+    fn square(name: &str, parent: Option<&str>, min_lon: f64, min_lat: f64, max_lon: f64, max_lat: f64) -> Region {
+        Region { parent: parent.map(String::from), ..region(name, rectangle(min_lon, min_lat, max_lon, max_lat)) }
+    }
+
+    #[test]
+    fn test_paths() {
+        let regions = vec![
+            square("Vilnius", Some("Vilnius County"), 25.0, 54.6, 25.4, 54.8),
+            square("Lithuania", None, 21.0, 53.9, 26.8, 56.4),
+            square("Vilnius County", Some("Lithuania"), 24.5, 54.2, 26.0, 55.4),
+            square("Kaunas County", Some("Lithuania"), 23.3, 54.6, 24.5, 55.4),
+        ];
+        let hierarchy = RegionHierarchy::new(&regions).unwrap();
+        let locations = vec![
+            Location { name: "Vilnius centre".into(), coordinates: coords(25.28, 54.68), ..Default::default() },
+            Location { name: "Kaunas".into(), coordinates: coords(23.9, 54.9), ..Default::default() },
+            Location { name: "Klaipeda".into(), coordinates: coords(21.1, 55.7), ..Default::default() },
+            Location { name: "Riga".into(), coordinates: coords(24.1, 56.9), ..Default::default() },
+        ];
+        let result = hierarchy.match_locations(&locations);
+
+        assert_eq!(result[0].paths, vec![vec!["Lithuania".to_owned(), "Vilnius County".to_owned(), "Vilnius".to_owned()]]);
+        assert_eq!(result[1].paths, vec![vec!["Lithuania".to_owned(), "Kaunas County".to_owned()]]);
+        assert_eq!(result[2].paths, vec![vec!["Lithuania".to_owned()]]);
+        assert!(result[3].paths.is_empty());
+    }
+
+    #[test]
+    fn test_child_not_contained_in_parent() {
+        let regions = vec![
+            square("Lithuania", None, 21.0, 53.9, 26.8, 56.4),
+            square("Riga", Some("Lithuania"), 23.9, 56.8, 24.3, 57.1),
+        ];
+        assert_eq!(RegionHierarchy::new(&regions).unwrap_err(), HierarchyError::NotContained { region: "Riga".into(), parent: "Lithuania".into() });
+    }
+
+    #[test]
+    fn test_unknown_parent_and_cycle() {
+        let regions = vec![square("Vilnius", Some("Lithuania"), 25.0, 54.6, 25.4, 54.8)];
+        assert_eq!(RegionHierarchy::new(&regions).unwrap_err(), HierarchyError::UnknownParent { region: "Vilnius".into(), parent: "Lithuania".into() });
+
+        let regions = vec![
+            square("a", Some("b"), 25.0, 54.6, 25.4, 54.8),
+            square("b", Some("a"), 25.0, 54.6, 25.4, 54.8),
+        ];
+        assert_eq!(RegionHierarchy::new(&regions).unwrap_err(), HierarchyError::Cycle("a".into()));
+    }
+}
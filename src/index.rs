@@ -0,0 +1,404 @@
+use std::{borrow::Cow, error::Error, ffi::OsString, fs::File, io::{self, Write}, path::Path};
+
+use bincode::Options;
+
+use chrono::{DateTime, Utc};
+use geo::{BoundingRect, Contains};
+use memmap2::Mmap;
+use rstar::{primitives::{GeomWithData, Rectangle}, RTree, AABB};
+use serde::{Deserialize, Serialize};
+
+use crate::{create_geo_polygons, hierarchy::{check_hierarchy, HierarchyError}, create_geopoint_from_coords, data_structures::{Coordinates, DecimalLat, DecimalLon, Location, MatchedResult, Polygon, Region}, CompiledRegion};
+
+const MAGIC: &[u8; 8] = b"TTREGIDX";
+/// Version of the index file format, files written in other versions are refused
+pub const FORMAT_VERSION: u32 = 1;
+// Magic, format version, checksum of the payload and its length
+const HEADER_LEN: usize = 8 + 4 + 4 + 8;
+
+#[derive(Debug)]
+pub enum IndexError {
+    Io(io::Error),
+    NotAnIndex,
+    UnsupportedVersion(u32),
+    ChecksumMismatch,
+    Corrupted(String),
+}
+
+impl Error for IndexError {}
+
+impl std::fmt::Display for IndexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            IndexError::Io(e) => write!(f, "Cannot access the index file, os: {}", e),
+            IndexError::NotAnIndex => write!(f, "The file is not a region index"),
+            IndexError::UnsupportedVersion(version) => write!(f, "The index has format version {}, only version {} is supported (rebuild it with the index subcommand)", version, FORMAT_VERSION),
+            IndexError::ChecksumMismatch => write!(f, "The index is damaged, its checksum does not match"),
+            IndexError::Corrupted(e) => write!(f, "The index is damaged! {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for IndexError {
+    fn from(e: io::Error) -> Self {
+        IndexError::Io(e)
+    }
+}
+
+/// Regions which cannot be matched against, see [`RegionIndex::validated`].
+#[derive(Debug)]
+pub enum RegionsError {
+    Invalid(serde_json::Error),
+    Hierarchy(HierarchyError),
+}
+
+impl Error for RegionsError {}
+
+impl std::fmt::Display for RegionsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RegionsError::Invalid(e) => write!(f, "Invalid regions! {}", e),
+            RegionsError::Hierarchy(e) => write!(f, "Invalid region hierarchy! {}", e),
+        }
+    }
+}
+
+// Bounding box of a polygon, with the indices of its region and of the polygon within the region
+type IndexEntry = GeomWithData<Rectangle<[f64; 2]>, (usize, usize)>;
+
+// Region fields which are not part of its geometry, the geometry is stored compiled
+#[derive(Serialize, Deserialize)]
+struct StoredRegion {
+    name: String,
+    holes: Vec<Vec<(f64, f64)>>,
+    buffer: Option<f64>,
+    priority: Option<i64>,
+    parent: Option<String>,
+    valid_from: Option<DateTime<Utc>>,
+    valid_to: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredIndex {
+    regions: Vec<StoredRegion>,
+    polygons: Vec<Vec<geo::Polygon>>,
+    tree: RTree<IndexEntry>,
+}
+
+/// Regions compiled into geometries together with an R-tree of their polygons' bounding boxes,
+/// so every location is only checked against polygons whose bounding box contains it.
+pub struct RegionIndex {
+    regions: Vec<Region>,
+    // Polygons of every region with their holes, as in [`create_geo_polygons`], and their bounding boxes
+    geometries: Vec<geo::MultiPolygon>,
+    bounds: Vec<Option<geo::Rect>>,
+    tree: RTree<IndexEntry>,
+}
+
+fn vertices(ring: &[(f64, f64)]) -> Result<Vec<Coordinates>, IndexError> {
+    ring.iter()
+        .map(|&(lon, lat)| Ok(Coordinates {
+            longitude: DecimalLon::new(lon).map_err(|e| IndexError::Corrupted(e.to_string()))?,
+            latitude: DecimalLat::new(lat).map_err(|e| IndexError::Corrupted(e.to_string()))?,
+        }))
+        .collect()
+}
+
+impl RegionIndex {
+    pub fn new(regions: Vec<Region>) -> RegionIndex {
+        let polygons: Vec<Vec<geo::Polygon>> = regions.iter().map(create_geo_polygons).collect();
+        let tree = RTree::bulk_load(RegionIndex::tree_entries(&polygons));
+        RegionIndex::from_parts(regions, polygons, tree)
+    }
+
+    fn tree_entries(polygons: &[Vec<geo::Polygon>]) -> Vec<IndexEntry> {
+        polygons.iter().enumerate()
+            .flat_map(|(region_idx, region_polygons)| region_polygons.iter().enumerate()
+                .filter_map(move |(polygon_idx, poly)| poly.bounding_rect().map(|rect| (rect, (region_idx, polygon_idx)))))
+            .map(|(rect, data)| GeomWithData::new(Rectangle::from_corners(rect.min().into(), rect.max().into()), data))
+            .collect()
+    }
+
+    fn from_parts(regions: Vec<Region>, polygons: Vec<Vec<geo::Polygon>>, tree: RTree<IndexEntry>) -> RegionIndex {
+        let geometries: Vec<geo::MultiPolygon> = polygons.into_iter().map(geo::MultiPolygon::new).collect();
+        let bounds = geometries.iter().map(|geometry| geometry.bounding_rect()).collect();
+        RegionIndex { regions, geometries, bounds, tree }
+    }
+
+    // The region with its geometry from the index, which is not compiled again
+    fn compiled(&self, idx: usize) -> CompiledRegion<'_> {
+        CompiledRegion { region: &self.regions[idx], geometry: Cow::Borrowed(&self.geometries[idx]), bounds: self.bounds[idx] }
+    }
+
+    /// Builds the index like the CLI does before matching, refusing regions with an invalid hierarchy (checked whenever regions declare parents).
+    pub fn validated(regions: Vec<Region>) -> Result<RegionIndex, RegionsError> {
+        check_hierarchy(&regions).map_err(RegionsError::Hierarchy)?;
+        Ok(RegionIndex::new(regions))
+    }
+
+    /// Same as [`RegionIndex::validated`] for JSON in the regions file format.
+    pub fn from_json(json: &[u8]) -> Result<RegionIndex, RegionsError> {
+        RegionIndex::validated(serde_json::from_slice(json).map_err(RegionsError::Invalid)?)
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    // Polygons whose bounding box contains the coordinates, as (region, polygon) indices in the order of regions and their polygons
+    fn candidates(&self, coordinates: &Coordinates) -> Vec<(usize, usize)> {
+        let point = [coordinates.longitude.val(), coordinates.latitude.val()];
+        let mut candidates: Vec<(usize, usize)> = self.tree.locate_in_envelope_intersecting(&AABB::from_point(point))
+            .map(|entry| entry.data)
+            .collect();
+        candidates.sort_unstable();
+        candidates
+    }
+
+    /// Same as [`crate::lookup_regions`].
+    pub fn lookup(&self, coordinates: &Coordinates, time: Option<&DateTime<Utc>>) -> Vec<&Region> {
+        self.lookup_indices(coordinates, time).into_iter().map(|idx| &self.regions[idx]).collect()
+    }
+
+    /// Same as [`RegionIndex::lookup`], but returns indices of the regions (in the order they were given).
+    pub fn lookup_indices(&self, coordinates: &Coordinates, time: Option<&DateTime<Utc>>) -> Vec<usize> {
+        let geo_point = create_geopoint_from_coords(coordinates);
+        let mut found: Vec<usize> = self.candidates(coordinates).into_iter()
+            .filter(|&(region_idx, polygon_idx)| self.regions[region_idx].buffer.is_none() && self.geometries[region_idx].0[polygon_idx].contains(&geo_point))
+            .map(|(region_idx, _)| region_idx)
+            .collect();
+        // Buffered regions reach outside their polygons' bounding boxes
+        found.extend((0..self.regions.len())
+            .filter(|&idx| self.regions[idx].buffer.is_some() && self.compiled(idx).covers(coordinates)));
+        found.sort_unstable();
+        found.dedup();
+        found.retain(|&idx| self.regions[idx].is_active_at(time));
+        found
+    }
+
+    /// Same as [`crate::match_locations_to_regions`], with the same order of matched locations.
+    pub fn match_locations(&self, locations: &[Location]) -> Vec<MatchedResult> {
+        let mut hits: Vec<(usize, usize, usize)> = vec![];
+        for (loc_idx, loc) in locations.iter().enumerate() {
+            let geo_point = create_geopoint_from_coords(&loc.coordinates);
+            hits.extend(self.candidates(&loc.coordinates).into_iter()
+                .filter(|&(region_idx, polygon_idx)| {
+                    let region = &self.regions[region_idx];
+                    region.buffer.is_none() && region.is_active_at(loc.timestamp.as_ref()) && self.geometries[region_idx].0[polygon_idx].contains(&geo_point)
+                })
+                .map(|(region_idx, polygon_idx)| (region_idx, polygon_idx, loc_idx)));
+        }
+        hits.sort_unstable();
+
+        let mut results: Vec<MatchedResult> = self.regions.iter()
+            .map(|region| MatchedResult { region: region.name.clone(), matched_locations: vec![], nearest_locations: vec![] })
+            .collect();
+        for (region_idx, _, loc_idx) in hits {
+            results[region_idx].matched_locations.push(locations[loc_idx].clone());
+        }
+        for (idx, result) in results.iter_mut().enumerate().filter(|&(idx, _)| self.regions[idx].buffer.is_some()) {
+            let region = self.compiled(idx);
+            result.matched_locations = locations.iter().filter(|loc| region.matches(loc)).cloned().collect();
+        }
+        results
+    }
+
+    /// Serializes the index into the versioned binary format, with a checksum of its content.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let stored = StoredIndex {
+            regions: self.regions.iter()
+                .map(|region| StoredRegion {
+                    name: region.name.clone(),
+                    holes: region.holes.iter()
+                        .map(|hole| hole.vertices.iter().map(|vert| (vert.longitude.val(), vert.latitude.val())).collect())
+                        .collect(),
+                    buffer: region.buffer,
+                    priority: region.priority,
+                    parent: region.parent.clone(),
+                    valid_from: region.valid_from,
+                    valid_to: region.valid_to,
+                })
+                .collect(),
+            polygons: self.geometries.iter().map(|geometry| geometry.0.clone()).collect(),
+            tree: self.tree.clone(),
+        };
+        let payload = bincode::serialize(&stored).expect("Index serializes into memory");
+
+        let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+        bytes.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+        bytes
+    }
+
+    /// Reads an index written by [`RegionIndex::to_bytes`], verifying its format version and checksum.
+    pub fn from_bytes(bytes: &[u8]) -> Result<RegionIndex, IndexError> {
+        if bytes.len() < HEADER_LEN || &bytes[..8] != MAGIC {
+            return Err(IndexError::NotAnIndex);
+        }
+        let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+        if version != FORMAT_VERSION {
+            return Err(IndexError::UnsupportedVersion(version));
+        }
+        let checksum = u32::from_le_bytes(bytes[12..16].try_into().unwrap());
+        let payload_len = u64::from_le_bytes(bytes[16..24].try_into().unwrap());
+        let payload = &bytes[HEADER_LEN..];
+        if payload.len() as u64 != payload_len || crc32fast::hash(payload) != checksum {
+            return Err(IndexError::ChecksumMismatch);
+        }
+
+        // Same encoding as bincode::serialize, limited to the payload so a damaged length cannot allocate more than the file holds
+        let stored: StoredIndex = bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .allow_trailing_bytes()
+            .with_limit(payload_len)
+            .deserialize(payload)
+            .map_err(|e| IndexError::Corrupted(e.to_string()))?;
+        if stored.polygons.len() != stored.regions.len() {
+            return Err(IndexError::Corrupted("Regions and their polygons do not match".into()));
+        }
+        let regions = stored.regions.into_iter().zip(&stored.polygons)
+            .map(|(region, polygons)| Ok(Region {
+                name: region.name,
+                polygons: polygons.iter()
+                    .map(|poly| Ok(Polygon { vertices: vertices(&poly.exterior().coords().map(|c| (c.x, c.y)).collect::<Vec<_>>())? }))
+                    .collect::<Result<_, IndexError>>()?,
+                holes: region.holes.iter().map(|hole| Ok(Polygon { vertices: vertices(hole)? })).collect::<Result<_, IndexError>>()?,
+                buffer: region.buffer,
+                priority: region.priority,
+                parent: region.parent,
+                valid_from: region.valid_from,
+                valid_to: region.valid_to,
+            }))
+            .collect::<Result<_, IndexError>>()?;
+        Ok(RegionIndex::from_parts(regions, stored.polygons, stored.tree))
+    }
+
+    /// Writes the index into a sibling file which then replaces the previous one at once,
+    /// so an index which is being loaded (and memory mapped) is never changed underneath.
+    pub fn write(&self, path: &Path) -> Result<(), IndexError> {
+        let mut temp_name = OsString::from(".");
+        temp_name.push(path.file_name().unwrap_or_default());
+        temp_name.push(format!(".{}.tmp", std::process::id()));
+        let temp_path = path.with_file_name(temp_name);
+
+        let result = File::create(&temp_path)
+            .and_then(|mut file| {
+                file.write_all(&self.to_bytes())?;
+                file.sync_all()
+            })
+            .and_then(|()| std::fs::rename(&temp_path, path));
+        if result.is_err() {
+            let _ = std::fs::remove_file(&temp_path);
+        }
+        Ok(result?)
+    }
+
+    /// Loads the index file through memory mapping.
+    pub fn load(path: &Path) -> Result<RegionIndex, IndexError> {
+        let file = File::open(path)?;
+        // SAFETY: the mapping is only read while the index is decoded into owned structures, and its content is verified by the checksum first.
+        // Index files are replaced by renaming (see write), so the mapped file itself is not modified while it is loaded.
+        let mmap = unsafe { Mmap::map(&file)? };
+        RegionIndex::from_bytes(&mmap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{match_locations_to_regions, test_utils::{coords, square}};
+
+    use super::*;
+
+    fn regions() -> Vec<Region> {
+        vec![
+            Region { name: "frame".into(), polygons: vec![square(0.0, 3.0), square(2.0, 4.0)], holes: vec![square(1.0, 2.0)], ..Default::default() },
+            Region { name: "small".into(), polygons: vec![square(0.0, 1.0)], valid_to: Some("2024-07-01T00:00:00Z".parse().unwrap()), ..Default::default() },
+            Region { name: "buffered".into(), polygons: vec![square(10.0, 11.0)], buffer: Some(1000.0), ..Default::default() },
+        ]
+    }
+
+    fn locations() -> Vec<Location> {
+        [(0.5, 0.5), (1.5, 1.5), (2.5, 2.5), (3.5, 3.5), (11.005, 10.5), (20.0, 20.0)].into_iter().enumerate()
+            .map(|(idx, (lon, lat))| Location { name: format!("Location {}", idx), coordinates: coords(lon, lat), ..Default::default() })
+            .chain([Location { name: "Late".into(), coordinates: coords(0.5, 0.5), timestamp: Some("2024-08-01T00:00:00Z".parse().unwrap()), ..Default::default() }])
+            .collect()
+    }
+
+    #[test]
+    fn test_index_matches_like_regions() {
+        let (regions, locations) = (regions(), locations());
+        let expected = match_locations_to_regions(&locations, &regions);
+        let index = RegionIndex::new(regions);
+
+        assert_eq!(serde_json::to_value(index.match_locations(&locations)).unwrap(), serde_json::to_value(expected).unwrap());
+        let names = |found: Vec<&Region>| found.iter().map(|region| region.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(index.lookup(&coords(0.5, 0.5), None)), vec!["frame", "small"]);
+        assert_eq!(names(index.lookup(&coords(1.5, 1.5), None)), Vec::<String>::new());
+        assert_eq!(names(index.lookup(&coords(11.005, 10.5), None)), vec!["buffered"]);
+        let found = index.lookup_indices(&coords(0.5, 0.5), None);
+        assert_eq!(found.iter().map(|&idx| index.regions()[idx].name.as_str()).collect::<Vec<_>>(), vec!["frame", "small"]);
+    }
+
+    #[test]
+    fn test_index_round_trip() {
+        let locations = locations();
+        let index = RegionIndex::new(regions());
+        let bytes = index.to_bytes();
+        let read_back = RegionIndex::from_bytes(&bytes).unwrap();
+
+        assert_eq!(read_back.regions()[0].polygons, index.regions()[0].polygons);
+        assert_eq!(read_back.regions()[0].holes, index.regions()[0].holes);
+        assert_eq!(read_back.regions()[1].valid_to, index.regions()[1].valid_to);
+        assert_eq!(serde_json::to_value(read_back.match_locations(&locations)).unwrap(), serde_json::to_value(index.match_locations(&locations)).unwrap());
+    }
+
+    #[test]
+    fn test_damaged_index() {
+        let mut bytes = RegionIndex::new(regions()).to_bytes();
+
+        assert!(matches!(RegionIndex::from_bytes(b"[{\"name\": \"a\"}]"), Err(IndexError::NotAnIndex)));
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert!(matches!(RegionIndex::from_bytes(&bytes), Err(IndexError::ChecksumMismatch)));
+        bytes[8..12].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(RegionIndex::from_bytes(&bytes), Err(IndexError::UnsupportedVersion(_))));
+    }
+
+    #[test]
+    fn test_oversized_length_is_refused() {
+        // A checksummed payload claiming far more regions than the file holds
+        let payload = u64::MAX.to_le_bytes();
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+        bytes.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+        assert!(matches!(RegionIndex::from_bytes(&bytes), Err(IndexError::Corrupted(_))));
+    }
+
+    #[test]
+    fn test_write_and_load() {
+        let path = std::env::temp_dir().join(format!("regions-{}.idx", std::process::id()));
+        RegionIndex::new(regions()).write(&path).unwrap();
+        // Replacing the index keeps the previously loaded one intact
+        let loaded = RegionIndex::load(&path).unwrap();
+        RegionIndex::new(regions().into_iter().take(1).collect()).write(&path).unwrap();
+
+        assert_eq!(loaded.regions().len(), regions().len());
+        assert_eq!(RegionIndex::load(&path).unwrap().regions().len(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_index_from_json_checks_hierarchy() {
+        let index = RegionIndex::from_json(br#"[{"name": "a", "coordinates": [[[0, 0], [1, 0], [1, 1], [0, 0]]]}]"#).unwrap();
+        assert_eq!(index.regions().len(), 1);
+
+        let orphan = br#"[{"name": "a", "coordinates": [[[0, 0], [1, 0], [1, 1], [0, 0]]], "parent": "b"}]"#;
+        assert!(matches!(RegionIndex::from_json(orphan), Err(RegionsError::Hierarchy(HierarchyError::UnknownParent { .. }))));
+        assert!(matches!(RegionIndex::from_json(b"[{\"name\": \"a\"}]"), Err(RegionsError::Invalid(_))));
+    }
+}
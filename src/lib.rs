@@ -1,41 +1,244 @@
-use data_structures::{Coordinates, Location, MatchedResult, Region};
-use geo::{coord, point, Contains};
+use std::borrow::Cow;
 
+use data_structures::{Coordinates, DecimalLat, DecimalLon, Location, MatchedResult, NearestLocation, Polygon, Region};
+use chrono::{DateTime, Utc};
+use geo::{coord, orient::Direction, point, BooleanOps, BoundingRect, Contains, GeodesicArea, Intersects, Orient};
+
+pub mod analysis;
 pub mod data_structures;
+pub mod distance;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod geojson;
+pub mod geometry;
+pub mod hierarchy;
+pub mod index;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod set_ops;
+pub mod simplify;
+pub mod stats;
+#[cfg(test)]
+mod test_utils;
+pub mod trajectory;
+pub mod validation;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
+#[allow(clippy::ptr_arg)]
 fn create_linestring_from_coord_vec(coords: &Vec<Coordinates>) -> geo::LineString {
     geo::LineString::new(coords.iter().map(|vert| coord! { x: vert.longitude.val(), y: vert.latitude.val() }).collect())
 }
 
+fn create_geopoint_from_coords(coords: &Coordinates) -> geo::Point {
+    point!(x: coords.longitude.val(), y: coords.latitude.val())
+}
+
 fn create_geopoint_from_loc(location: &Location) -> geo::Point {
-    point!(x: location.coordinates.longitude.val(), y: location.coordinates.latitude.val())
+    create_geopoint_from_coords(&location.coordinates)
+}
+
+// Whether the hole lies within the outline of any of the polygons
+fn hole_within_polygons(hole: &Polygon, polygons: &[Polygon]) -> bool {
+    let hole = create_linestring_from_coord_vec(&hole.vertices);
+    polygons.iter().any(|poly| geo::Polygon::new(create_linestring_from_coord_vec(&poly.vertices), vec![]).contains(&hole))
+}
+
+// Every hole of the region is cut out of the polygons it lies within, so islands inside a hole stay untouched
+fn create_geo_polygons(region: &Region) -> Vec<geo::Polygon> {
+    let holes: Vec<geo::LineString> = region.holes.iter().map(|hole| create_linestring_from_coord_vec(&hole.vertices)).collect();
+    region.polygons.iter()
+        .map(|poly| {
+            let exterior = create_linestring_from_coord_vec(&poly.vertices);
+            let outline = geo::Polygon::new(exterior.clone(), vec![]);
+            geo::Polygon::new(exterior, holes.iter().filter(|hole| outline.contains(*hole)).cloned().collect())
+        })
+        .collect()
+}
+
+fn create_geo_multipolygon(region: &Region) -> geo::MultiPolygon {
+    geo::MultiPolygon::new(create_geo_polygons(region))
+}
+
+// Region's polygons may overlap each other, so they are united into a valid multipolygon
+fn region_union(region: &Region) -> geo::MultiPolygon {
+    create_geo_polygons(region).into_iter()
+        .fold(geo::MultiPolygon::new(vec![]), |union, poly| union.union(&geo::MultiPolygon::new(vec![poly])))
+}
+
+// Exteriors become the region's polygons, all the interiors become its holes
+fn create_region_from_multipolygon(name: String, multipolygon: &geo::MultiPolygon) -> Region {
+    Region {
+        name,
+        polygons: multipolygon.iter().map(|poly| create_polygon_from_linestring(poly.exterior())).collect(),
+        holes: multipolygon.iter().flat_map(|poly| poly.interiors()).map(create_polygon_from_linestring).collect(),
+        ..Default::default()
+    }
+}
+
+fn create_polygon_from_linestring(linestring: &geo::LineString) -> Polygon {
+    Polygon {
+        vertices: linestring.coords()
+            .map(|vert| Coordinates {
+                longitude: DecimalLon::new(vert.x).expect("Geometry operations do not leave the range of longitudes"),
+                latitude: DecimalLat::new(vert.y).expect("Geometry operations do not leave the range of latitudes"),
+            })
+            .collect()
+    }
+}
+
+// The longitude shifted by whole turns to lie within 180° of the reference longitude
+fn unwrap_longitude(lon: f64, reference: f64) -> f64 {
+    let mut lon = lon;
+    while lon - reference > 180.0 {
+        lon -= 360.0;
+    }
+    while lon - reference < -180.0 {
+        lon += 360.0;
+    }
+    lon
+}
+
+// Unwrapped averages can leave the valid range, they are brought back to [-180, 180]
+fn wrap_longitude(lon: f64) -> f64 {
+    match lon {
+        lon if lon > 180.0 => lon - 360.0,
+        lon if lon < -180.0 => lon + 360.0,
+        lon => lon,
+    }
+}
+
+/// A region with its geometry compiled once, so matching many locations against it does not rebuild the polygons every time.
+#[derive(Debug)]
+pub(crate) struct CompiledRegion<'a> {
+    pub(crate) region: &'a Region,
+    // Polygons of the region with their holes, as in create_geo_polygons
+    pub(crate) geometry: Cow<'a, geo::MultiPolygon>,
+    pub(crate) bounds: Option<geo::Rect>,
 }
 
-pub fn match_locations_to_regions(locations: &Vec<Location>, regions: &Vec<Region>) -> Vec<MatchedResult> {
+impl<'a> CompiledRegion<'a> {
+    pub(crate) fn new(region: &'a Region) -> CompiledRegion<'a> {
+        let geometry = create_geo_multipolygon(region);
+        let bounds = geometry.bounding_rect();
+        CompiledRegion { region, geometry: Cow::Owned(geometry), bounds }
+    }
+
+    pub(crate) fn contains(&self, coordinates: &Coordinates) -> bool {
+        let geo_point = create_geopoint_from_coords(coordinates);
+        self.bounds.is_some_and(|bounds| bounds.intersects(&geo_point))
+            && self.geometry.iter().any(|poly| poly.contains(&geo_point))
+    }
+
+    // Takes the region's buffer into account, if it has one
+    pub(crate) fn covers(&self, coordinates: &Coordinates) -> bool {
+        match self.region.buffer {
+            Some(buffer) => self.buffered_contains(coordinates, buffer),
+            None => self.contains(coordinates),
+        }
+    }
+
+    // The location has to be inside the region and the region has to be active at the location's timestamp
+    pub(crate) fn matches(&self, location: &Location) -> bool {
+        self.region.is_active_at(location.timestamp.as_ref()) && self.covers(&location.coordinates)
+    }
+}
+
+fn compile_regions(regions: &[Region]) -> Vec<CompiledRegion<'_>> {
+    regions.iter().map(CompiledRegion::new).collect()
+}
+
+fn locations_in_region(locations: &[Location], region: &Region) -> Vec<Location> {
+    if region.buffer.is_some() {
+        let region = CompiledRegion::new(region);
+        return locations.iter().filter(|loc| region.matches(loc)).cloned().collect();
+    }
+    create_geo_polygons(region).into_iter()
+        .flat_map(
+            |geo_polygon| {
+            locations.iter().filter(move |loc| region.is_active_at(loc.timestamp.as_ref()) && geo_polygon.contains(&create_geopoint_from_loc(loc))) // Select these locations, which match the polygon
+        })
+        .cloned()
+        .collect()
+}
+
+pub fn match_locations_to_regions(locations: &[Location], regions: &[Region]) -> Vec<MatchedResult> {
     regions.iter().map(
         |region|
         MatchedResult { 
             region: region.name.clone(),
-            matched_locations: region.polygons.iter()
-                .flat_map(
-                    |poly| {
-                    let geo_polygon = geo::Polygon::new(
-                        create_linestring_from_coord_vec(&poly.vertices), vec![]
-                    );
-                    locations.iter().filter(move |loc| geo_polygon.contains(&create_geopoint_from_loc(loc))) // Select these locations, which match the polygon
-                })
-                .cloned()
-                .collect()
+            matched_locations: locations_in_region(locations, region),
+            nearest_locations: vec![],
         }
     ).collect() // With this construct, I avoid creating mutable Vec which would have been continuously filled in a loop
 }
 
+/// Geodesic area (in square metres) of all the region's polygons, without holes.
+pub fn region_area(region: &Region) -> f64 {
+    // The geodesic area of a clockwise ring is the rest of the Earth, so rings are oriented first
+    create_geo_polygons(region).iter()
+        .map(|poly| poly.orient(Direction::Default).geodesic_area_unsigned())
+        .sum()
+}
+
+/// Same as [`match_locations_to_regions`], but every location is assigned to at most one region.
+/// When a location matches several regions, the one with the highest `priority` wins (regions without priority lose to any with it),
+/// then the one with the smallest area, then the one which comes first in `regions`.
+pub fn match_locations_to_regions_exclusively(locations: &[Location], regions: &[Region]) -> Vec<MatchedResult> {
+    let areas: Vec<f64> = regions.iter().map(region_area).collect();
+    let compiled = compile_regions(regions);
+    let mut results: Vec<MatchedResult> = regions.iter()
+        .map(|region| MatchedResult { region: region.name.clone(), matched_locations: vec![], nearest_locations: vec![] })
+        .collect();
+    locations.iter()
+        .filter_map(|loc| {
+            compiled.iter().enumerate()
+                .filter(|(_, region)| region.matches(loc))
+                .map(|(idx, compiled)| (idx, compiled.region))
+                .min_by(|(a_idx, a), (b_idx, b)|
+                    b.priority.cmp(&a.priority)
+                        .then(areas[*a_idx].total_cmp(&areas[*b_idx]))
+                        .then(a_idx.cmp(b_idx))
+                )
+                .map(|(idx, _)| (idx, loc))
+        })
+        .for_each(|(idx, loc)| results[idx].matched_locations.push(loc.clone()));
+    results
+}
+
+/// Same as [`match_locations_to_regions`], but every location which is not inside any region is additionally
+/// assigned to the nearest region (within `max_distance` metres, if given) and listed in its `nearest_locations`.
+pub fn match_locations_to_regions_with_nearest(locations: &[Location], regions: &[Region], max_distance: Option<f64>) -> Vec<MatchedResult> {
+    add_nearest_locations(match_locations_to_regions(locations, regions), locations, regions, max_distance)
+}
+
+/// Assigns every location which is not inside any region to the nearest region (active at the location's timestamp) (within `max_distance` metres, if given).
+/// `results` have to be in the same order as `regions`.
+pub fn add_nearest_locations(mut results: Vec<MatchedResult>, locations: &[Location], regions: &[Region], max_distance: Option<f64>) -> Vec<MatchedResult> {
+    let compiled = compile_regions(regions);
+    locations.iter()
+        .filter(|loc| !compiled.iter().any(|region| region.matches(loc)))
+        .filter_map(|loc| distance::nearest_region_index(&loc.coordinates, &compiled, max_distance, loc.timestamp.as_ref()).map(|nearest| (loc, nearest)))
+        .for_each(|(loc, (idx, distance))| results[idx].nearest_locations.push(NearestLocation { location: loc.clone(), distance }));
+    results
+}
+
+/// Regions (active at `time`, if given) which contain the coordinates, in the order of `regions`.
+pub fn lookup_regions<'a>(coordinates: &Coordinates, regions: &'a [Region], time: Option<&DateTime<Utc>>) -> Vec<&'a Region> {
+    regions.iter()
+        .filter(|region| region.is_active_at(time) && CompiledRegion::new(region).covers(coordinates))
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use data_structures::Coordinates;
     use data_structures::DecimalLat;
     use data_structures::DecimalLon;
     use data_structures::Polygon;
+    use test_utils::{coords, rectangle, region, square};
 
     use super::*;
     #[test]
@@ -65,6 +268,7 @@ mod tests {
                         ],
                     },
                 ],
+                ..Default::default()
             },
         ];
         let locations = vec![
@@ -74,6 +278,7 @@ mod tests {
                     latitude: DecimalLat::new(-81.3215926).unwrap(),
                     longitude: DecimalLon::new(55.1074219).unwrap(),
                 },
+                ..Default::default()
             },
             Location {
                 name: "Location 2".into(),
@@ -81,6 +286,7 @@ mod tests {
                     latitude: DecimalLat::new(-80.2979271).unwrap(),
                     longitude: DecimalLon::new(126.2109375).unwrap(),
                 },
+                ..Default::default()
             },
             Location {
                 name: "Location 3".into(),
@@ -88,6 +294,7 @@ mod tests {
                     latitude: DecimalLat::new(-82.6313329).unwrap(),
                     longitude: DecimalLon::new(74.1796875).unwrap(),
                 },
+                ..Default::default()
             },
             Location {
                 name: "Location 4".into(),
@@ -95,6 +302,7 @@ mod tests {
                     latitude: DecimalLat::new(-82.0700282).unwrap(),
                     longitude: DecimalLon::new(-125.5078125).unwrap(),
                 },
+                ..Default::default()
             },
             Location {
                 name: "Location 5".into(),
@@ -102,6 +310,7 @@ mod tests {
                     latitude: DecimalLat::new(-74.4964131).unwrap(),
                     longitude: DecimalLon::new(66.09375).unwrap(),
                 },
+                ..Default::default()
             },
         ];
         let matched_result = match_locations_to_regions(&locations, &regions);
@@ -144,6 +353,7 @@ mod tests {
                         ],
                     },
                 ],
+                ..Default::default()
             },
         ];
 
@@ -154,6 +364,7 @@ mod tests {
                     latitude: DecimalLat::new(15.9375).unwrap(),
                     longitude: DecimalLon::new(192.3397).unwrap(),
                 },
+                ..Default::default()
             },
             Location {
                 name: "Location 2".into(),
@@ -161,6 +372,7 @@ mod tests {
                     latitude: DecimalLat::new(14.3281).unwrap(),
                     longitude: DecimalLon::new(209.0477).unwrap(),
                 },
+                ..Default::default()
             },
             Location {
                 name: "Location 3".into(),
@@ -168,6 +380,7 @@ mod tests {
                     latitude: DecimalLat::new(30.0071).unwrap(),
                     longitude: DecimalLon::new(202.5000).unwrap(),
                 },
+                ..Default::default()
             },
             Location {
                 name: "Location 4".into(),
@@ -175,6 +388,7 @@ mod tests {
                     latitude: DecimalLat::new(15.4924).unwrap(),
                     longitude: DecimalLon::new(189.1316).unwrap(),
                 },
+                ..Default::default()
             },
             Location {
                 name: "Location 5".into(),
@@ -182,6 +396,7 @@ mod tests {
                     latitude: DecimalLat::new(14.9922).unwrap(),
                     longitude: DecimalLon::new(-179.4427).unwrap(),
                 },
+                ..Default::default()
             },
             Location {
                 name: "Location 6".into(),
@@ -189,6 +404,7 @@ mod tests {
                     latitude: DecimalLat::new(3.6359).unwrap(),
                     longitude: DecimalLon::new(182.4744).unwrap(),
                 },
+                ..Default::default()
             },
         ];
         let matched_result = match_locations_to_regions(&locations, &regions);
@@ -197,6 +413,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::useless_vec)]
     fn test_region_with_multiple_polygons() {
         let regions = vec![
             Region {
@@ -271,6 +488,7 @@ mod tests {
                         ],
                     },
                 ],
+                ..Default::default()
             },
         ];
         let locations = vec![
@@ -280,6 +498,7 @@ mod tests {
                     latitude: DecimalLat::new(53.931943242940264).unwrap(),
                     longitude: DecimalLon::new(18.04777597110123).unwrap(),
                 },
+                ..Default::default()
             },
             Location { //ok
                 name: "Location 2".into(),
@@ -287,6 +506,7 @@ mod tests {
                     latitude: DecimalLat::new(53.7702198592585).unwrap(),
                     longitude: DecimalLon::new(17.55344865541619).unwrap(),
                 },
+                ..Default::default()
             },
             Location { //ok
                 name: "Location 3".into(),
@@ -294,6 +514,7 @@ mod tests {
                     latitude: DecimalLat::new(54.08518088704679).unwrap(),
                     longitude: DecimalLon::new(17.84555741202533).unwrap(),
                 },
+                ..Default::default()
             },
             Location {
                 name: "Location 4".into(),
@@ -301,6 +522,7 @@ mod tests {
                     latitude: DecimalLat::new(53.903982576424795).unwrap(),
                     longitude: DecimalLon::new(17.590412921065422).unwrap(),
                 },
+                ..Default::default()
             }
         ];
         let matched_result = match_locations_to_regions(&locations, &regions);
@@ -311,6 +533,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::useless_vec)]
     fn test_point_on_overlapping_regions() {
         let regions = vec![
             Region {
@@ -345,6 +568,7 @@ mod tests {
                         ],
                     },
                 ],
+                ..Default::default()
             },
             Region {
                 name: "tatry_poland_slovakia".into(),
@@ -378,6 +602,7 @@ mod tests {
                         ],
                     }
                 ],
+                ..Default::default()
             }
         ];
         let locations = vec![
@@ -387,6 +612,7 @@ mod tests {
                     latitude: DecimalLat::new(49.24340413142335).unwrap(),
                     longitude: DecimalLon::new(19.726640710592307).unwrap(),
                 },
+                ..Default::default()
             },
             Location { //region1
                 name: "Location 2".into(),
@@ -394,6 +620,7 @@ mod tests {
                     latitude: DecimalLat::new(49.232581877359536).unwrap(),
                     longitude: DecimalLon::new(19.36788978252892).unwrap(),
                 },
+                ..Default::default()
             },
             Location { //region2
                 name: "Location 3".into(),
@@ -401,6 +628,7 @@ mod tests {
                     latitude: DecimalLat::new(49.24476375835607).unwrap(),
                     longitude: DecimalLon::new(20.219267732042425).unwrap(),
                 },
+                ..Default::default()
             },
             Location { //out
                 name: "Location 4".into(),
@@ -408,6 +636,7 @@ mod tests {
                     latitude: DecimalLat::new(49.399912837692284).unwrap(),
                     longitude: DecimalLon::new(19.561924809724104).unwrap(),
                 },
+                ..Default::default()
             }
         ];
         let matched_result = match_locations_to_regions(&locations, &regions);
@@ -420,6 +649,146 @@ mod tests {
         assert!(matched_result[1].matched_locations.iter().all(|loc| expected_region2.contains(loc)));
         assert!(matched_result[1].matched_locations.len() == expected_region2.len());
     }
-    
-    
-}
\ No newline at end of file
+
+    #[test]
+    fn test_unmatched_locations_assigned_to_nearest_region() {
+        let regions = vec![
+            Region {
+                name: "tatry_slovakia".into(),
+                polygons: vec![
+                    Polygon {
+                        vertices: vec![
+                            Coordinates {
+                                latitude: DecimalLat::new(49.37351801413155).unwrap(),
+                                longitude: DecimalLon::new(19.67847490452553).unwrap(),
+                            },
+                            Coordinates {
+                                latitude: DecimalLat::new(49.23803996442288).unwrap(),
+                                longitude: DecimalLon::new(19.304812334103275).unwrap(),
+                            },
+                            Coordinates {
+                                latitude: DecimalLat::new(49.097478621327554).unwrap(),
+                                longitude: DecimalLon::new(19.328347447593416).unwrap(),
+                            },
+                            Coordinates {
+                                latitude: DecimalLat::new(49.37351801413155).unwrap(),
+                                longitude: DecimalLon::new(19.67847490452553).unwrap(),
+                            },
+                        ],
+                    },
+                ],
+                ..Default::default()
+            },
+        ];
+        let locations = vec![
+            Location { //in
+                name: "Location 1".into(),
+                coordinates: Coordinates {
+                    latitude: DecimalLat::new(49.232581877359536).unwrap(),
+                    longitude: DecimalLon::new(19.36788978252892).unwrap(),
+                },
+                ..Default::default()
+            },
+            Location { //close
+                name: "Location 2".into(),
+                coordinates: Coordinates {
+                    latitude: DecimalLat::new(49.399912837692284).unwrap(),
+                    longitude: DecimalLon::new(19.561924809724104).unwrap(),
+                },
+                ..Default::default()
+            },
+            Location { //far away
+                name: "Location 3".into(),
+                coordinates: Coordinates {
+                    latitude: DecimalLat::new(52.2297).unwrap(),
+                    longitude: DecimalLon::new(21.0122).unwrap(),
+                },
+                ..Default::default()
+            },
+        ];
+        let matched_result = match_locations_to_regions_with_nearest(&locations, &regions, Some(50_000.0));
+
+        assert_eq!(matched_result[0].matched_locations, vec![locations[0].clone()]);
+        assert_eq!(matched_result[0].nearest_locations.len(), 1);
+        assert_eq!(matched_result[0].nearest_locations[0].location, locations[1]);
+        assert!(matched_result[0].nearest_locations[0].distance > 0.0);
+        assert!(matched_result[0].nearest_locations[0].distance < 50_000.0);
+    }
+
+    #[test]
+    fn test_exclusive_assignment() {
+        let square = |name: &str, min_lon: f64, max_lon: f64, priority: Option<i64>| Region {
+            priority,
+            ..region(name, rectangle(min_lon, 0.0, max_lon, 1.0))
+        };
+        let location = |name: &str, lon: f64| Location { name: name.into(), coordinates: coords(lon, 0.5), ..Default::default() };
+        let locations = vec![location("Location 1", 0.5), location("Location 2", 1.5), location("Location 3", 5.0)];
+
+        // The smaller region wins when there are no priorities
+        let regions = vec![square("big", 0.0, 2.0, None), square("small", 0.0, 1.0, None)];
+        let matched_result = match_locations_to_regions_exclusively(&locations, &regions);
+        assert_eq!(matched_result[0].matched_locations, vec![locations[1].clone()]);
+        assert_eq!(matched_result[1].matched_locations, vec![locations[0].clone()]);
+
+        // The declared priority wins over the area
+        let regions = vec![square("big", 0.0, 2.0, Some(1)), square("small", 0.0, 1.0, None)];
+        let matched_result = match_locations_to_regions_exclusively(&locations, &regions);
+        assert_eq!(matched_result[0].matched_locations, vec![locations[0].clone(), locations[1].clone()]);
+        assert!(matched_result[1].matched_locations.is_empty());
+
+        // Identical regions are resolved by the order in the file
+        let regions = vec![square("first", 0.0, 1.0, None), square("second", 0.0, 1.0, None)];
+        let matched_result = match_locations_to_regions_exclusively(&locations, &regions);
+        assert_eq!(matched_result[0].matched_locations, vec![locations[0].clone()]);
+        assert!(matched_result[1].matched_locations.is_empty());
+    }
+
+    #[test]
+    fn test_exclusive_assignment_with_clockwise_region() {
+        let ring = |vertices: &[(f64, f64)]| Polygon { vertices: vertices.iter().map(|&(lon, lat)| coords(lon, lat)).collect() };
+        let location = Location { name: "Location 1".into(), coordinates: coords(0.5, 0.5), ..Default::default() };
+        let big = region("big", ring(&[(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0), (0.0, 0.0)]));
+        let small = region("small", ring(&[(0.0, 0.0), (0.0, 1.0), (1.0, 1.0), (1.0, 0.0), (0.0, 0.0)]));
+        assert!(region_area(&small) < region_area(&big));
+
+        // The small region wins although it is wound clockwise
+        let locations = vec![location];
+        let matched_result = match_locations_to_regions_exclusively(&locations, &[big, small]);
+        assert!(matched_result[0].matched_locations.is_empty());
+        assert_eq!(matched_result[1].matched_locations, locations);
+    }
+
+    #[test]
+    fn test_time_windowed_regions() {
+        let zone = |name: &str, valid_from: &str, valid_to: &str| Region {
+            valid_from: Some(valid_from.parse().unwrap()),
+            valid_to: Some(valid_to.parse().unwrap()),
+            ..region(name, rectangle(0.0, 0.0, 1.0, 1.0))
+        };
+        let regions = vec![
+            zone("morning", "2024-07-01T06:00:00Z", "2024-07-01T12:00:00Z"),
+            zone("afternoon", "2024-07-01T12:00:00Z", "2024-07-01T18:00:00Z"),
+        ];
+        let locations = vec![
+            Location { name: "Location 1".into(), coordinates: coords(0.5, 0.5), timestamp: Some("2024-07-01T08:00:00Z".parse().unwrap()), ..Default::default() },
+            Location { name: "Location 2".into(), coordinates: coords(0.5, 0.5), timestamp: Some("2024-07-01T12:00:00Z".parse().unwrap()), ..Default::default() },
+            Location { name: "Location 3".into(), coordinates: coords(0.5, 0.5), timestamp: Some("2024-07-01T20:00:00Z".parse().unwrap()), ..Default::default() },
+            Location { name: "Location 4".into(), coordinates: coords(0.5, 0.5), ..Default::default() },
+        ];
+        let matched_result = match_locations_to_regions(&locations, &regions);
+
+        assert_eq!(matched_result[0].matched_locations, vec![locations[0].clone(), locations[3].clone()]);
+        assert_eq!(matched_result[1].matched_locations, vec![locations[1].clone(), locations[3].clone()]);
+    }
+
+    #[test]
+    fn test_lookup_regions() {
+        let mut regions = vec![region("big", square(0.0, 2.0)), region("small", square(0.0, 1.0)), region("far", square(5.0, 6.0))];
+        regions[1].valid_to = Some("2024-07-01T00:00:00Z".parse().unwrap());
+
+        let names = |found: Vec<&Region>| found.iter().map(|region| region.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(lookup_regions(&coords(0.5, 0.5), &regions, None)), vec!["big", "small"]);
+        assert_eq!(names(lookup_regions(&coords(0.5, 0.5), &regions, Some(&"2024-08-01T00:00:00Z".parse().unwrap()))), vec!["big"]);
+        assert!(lookup_regions(&coords(3.0, 3.0), &regions, None).is_empty());
+    }
+}
@@ -1,55 +1,1117 @@
-use std::{fs::File, io::{BufReader, BufWriter, Write}, path::PathBuf, process::ExitCode};
+use std::{collections::HashMap, ffi::OsString, fs::File, io::{self, BufRead, BufReader, BufWriter, Write}, path::{Path, PathBuf}, process::ExitCode, time::{Duration, SystemTime}};
 
-use clap::Parser;
-use traveltime_internship_task::{data_structures::{Location, Region}, match_locations_to_regions};
+use chrono::{DateTime, TimeDelta, Utc};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use flate2::{bufread::MultiGzDecoder, write::GzEncoder};
+use serde::Serialize;
+use traveltime_internship_task::{analysis::analyze_regions, geojson::{locations_from_geojson, locations_to_geojson, regions_from_geojson, regions_to_geojson}, data_structures::{Coordinates, DecimalLat, DecimalLon, Location, Region}, distance::{distance_to_region_boundary, nearest_region, with_distance_to_boundary}, geometry::{describe_regions, RegionProperties}, hierarchy::RegionHierarchy, index::RegionIndex, server::MatchServer, set_ops::{combine_regions_by_name, SetOperation}, simplify::{simplify_regions, SimplificationReport}, stats::match_stats, trajectory::{detect_crossings, detect_events}, validation::validate_regions, add_nearest_locations, lookup_regions, match_locations_to_regions, match_locations_to_regions_exclusively};
 
 #[derive(Parser)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
-    /// locations.json path
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    // Without a subcommand, locations are matched to regions
+    #[command(flatten)]
+    match_args: Option<MatchArgs>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Match locations to the regions containing them (the default when no subcommand is given)
+    Match(MatchArgs),
+    /// Check regions (and locations) files for problems, exits with 1 if any is found
+    Validate(ValidateArgs),
+    /// Convert regions or locations files to GeoJSON, or GeoJSON back to them
+    Convert(ConvertArgs),
+    /// Write per-region statistics (counts, shares, centroids, densities, weights) of the matched locations
+    Stats(StatsArgs),
+    /// Print the regions which contain the given points
+    Lookup(LookupArgs),
+    /// Print geodesic area, perimeter, centroid and bounding box of every region
+    Describe(DescribeArgs),
+    /// Report overlaps between regions and gaps they leave inside a coverage boundary
+    Analyze(AnalyzeArgs),
+    /// Build a new region as the union, intersection or difference of existing regions
+    Combine(CombineArgs),
+    /// Serve matching over HTTP: POST /match, GET /lookup?lat=&lon= and GET /regions
+    Serve(ServeArgs),
+    /// Build a binary region index file, which the match subcommand loads faster than regions files
+    Index(IndexArgs),
+    /// Simplify region boundaries, reporting the vertex reduction and the deviation introduced for every region
+    Simplify(SimplifyArgs),
+}
+
+#[derive(Args)]
+struct ValidateArgs {
+    /// regions.json path or glob pattern, can be given several times
+    #[arg(short, long, value_name="FILE", required=true)]
+    regions: Vec<PathBuf>,
+
+    /// locations.json path (or NDJSON) or glob pattern, - for stdin, can be given several times
+    #[arg(short, long, value_name="FILE")]
+    locations: Vec<PathBuf>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ConvertKind {
+    Regions,
+    Locations,
+}
+
+#[derive(Args)]
+struct ConvertArgs {
+    /// whether the input holds regions or locations
+    kind: ConvertKind,
+
+    /// input file path, a GeoJSON feature collection is converted to the regions.json/locations.json format and vice versa
+    #[arg(short, long, value_name="FILE")]
+    input: PathBuf,
+
+    /// output file path, - for stdout
+    #[arg(short, long, value_name="FILE")]
+    output: PathBuf,
+}
+
+#[derive(Args)]
+struct StatsArgs {
+    /// locations.json path (or NDJSON) or glob pattern, - for stdin, can be given several times
+    #[arg(short, long, value_name="FILE", required=true)]
+    locations: Vec<PathBuf>,
+
+    /// regions.json path or glob pattern, can be given several times
+    #[arg(short, long, value_name="FILE", required=true)]
+    regions: Vec<PathBuf>,
+
+    /// output file path, - for stdout
+    #[arg(short, long, value_name="FILE")]
+    output: PathBuf,
+
+    /// grow (or shrink, if negative) every region by this distance (in metres), unless the region declares its own buffer
+    #[arg(long, value_name="METRES", allow_negative_numbers=true)]
+    buffer: Option<f64>,
+
+    /// evaluate all locations at this time (RFC 3339), instead of their own timestamps
+    #[arg(long, value_name="TIME")]
+    at: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DescribeFormat {
+    Table,
+    Json,
+}
+
+#[derive(Args)]
+struct AnalyzeArgs {
+    /// regions.json path or glob pattern, can be given several times
+    #[arg(short, long, value_name="FILE", required=true)]
+    regions: Vec<PathBuf>,
+
+    /// file (in the regions.json format) with regions which together form the boundary the regions should cover
+    #[arg(short, long, value_name="FILE")]
+    coverage: Option<PathBuf>,
+
+    /// output file path, - for stdout
+    #[arg(short, long, value_name="FILE")]
+    output: PathBuf,
+}
+
+#[derive(Args)]
+struct CombineArgs {
+    /// operation to perform: union, intersection or difference (the first region minus all the others)
+    operation: SetOperation,
+
+    /// names of the regions to combine
+    #[arg(required = true)]
+    region_names: Vec<String>,
+
+    /// regions.json path or glob pattern, can be given several times
+    #[arg(short, long, value_name="FILE", required=true)]
+    regions: Vec<PathBuf>,
+
+    /// output file path (written in the regions.json format), - for stdout
     #[arg(short, long, value_name="FILE")]
-    locations: PathBuf,
+    output: PathBuf,
+
+    /// name of the new region
+    #[arg(short, long)]
+    name: String,
+
+    /// write all the input regions followed by the new one, instead of just the new region
+    #[arg(long)]
+    append: bool,
+}
+
+#[derive(Args)]
+struct SimplifyArgs {
+    /// regions.json path or glob pattern, can be given several times
+    #[arg(short, long, value_name="FILE", required=true)]
+    regions: Vec<PathBuf>,
+
+    /// maximum distance (in metres) a removed vertex may lie from the simplified boundary
+    #[arg(short, long, value_name="METRES")]
+    tolerance: f64,
+
+    /// output file path (written in the regions.json format), - for stdout
+    #[arg(short, long, value_name="FILE")]
+    output: PathBuf,
+}
+
+#[derive(Args)]
+struct DescribeArgs {
+    /// regions.json path or glob pattern, can be given several times
+    #[arg(short, long, value_name="FILE", required=true)]
+    regions: Vec<PathBuf>,
+
+    /// how the properties are printed
+    #[arg(short, long, value_enum, default_value_t=DescribeFormat::Table)]
+    format: DescribeFormat,
+}
+
+#[derive(Args)]
+struct LookupArgs {
+    /// points to look up, as latitude,longitude (e.g. 54.68,25.27 or -33.92,18.42)
+    #[arg(required = true, value_name="LAT,LON", value_parser=parse_point, allow_hyphen_values=true)]
+    points: Vec<Coordinates>,
+
+    /// regions.json path or glob pattern, can be given several times
+    #[arg(short, long, value_name="FILE", required=true)]
+    regions: Vec<PathBuf>,
+
+    /// also print the distance (in metres) from the point to the boundary of every matching region, or to the nearest region if none matches
+    #[arg(long)]
+    boundary_distance: bool,
+
+    /// only consider regions active at this time (RFC 3339)
+    #[arg(long, value_name="TIME")]
+    at: Option<DateTime<Utc>>,
+}
+
+// Points with a negative latitude look like flags, which clap would take as points once it is reading them,
+// so all the points of lookup are moved after `--` (in their order), where they are values wherever they were given
+fn points_last(args: Vec<OsString>) -> Vec<OsString> {
+    if args.get(1).and_then(|arg| arg.to_str()) != Some("lookup") {
+        return args
+    }
+    let (points, mut rest): (Vec<OsString>, Vec<OsString>) = args.into_iter()
+        .partition(|arg| arg.to_str().is_some_and(|arg| parse_point(arg).is_ok()));
+    rest.retain(|arg| arg != "--");
+    rest.push("--".into());
+    rest.extend(points);
+    rest
+}
+
+fn parse_point(point: &str) -> Result<Coordinates, String> {
+    let (lat, lon) = point.split_once(',').ok_or("the point should be given as latitude,longitude")?;
+    let parse = |value: &str| value.trim().parse::<f64>().map_err(|e| format!("{}: {}", value.trim(), e));
+    Ok(Coordinates {
+        longitude: DecimalLon::new(parse(lon)?).map_err(|e| e.to_string())?,
+        latitude: DecimalLat::new(parse(lat)?).map_err(|e| e.to_string())?,
+    })
+}
+
+#[derive(Args)]
+struct ServeArgs {
+    /// regions.json path or glob pattern, can be given several times
+    #[arg(short, long, value_name="FILE", required_unless_present="index")]
+    regions: Vec<PathBuf>,
+
+    /// region index file (built by the index subcommand) to use instead of regions files
+    #[arg(long, value_name="FILE", conflicts_with="regions")]
+    index: Option<PathBuf>,
+
+    /// address to listen on
+    #[arg(short, long, default_value="127.0.0.1:8080")]
+    address: String,
+
+    /// number of threads answering requests (defaults to the number of CPUs)
+    #[arg(long)]
+    workers: Option<usize>,
+}
 
-    /// regions.json path
+#[derive(Args)]
+struct IndexArgs {
+    /// regions.json path or glob pattern, can be given several times
+    #[arg(short, long, value_name="FILE", required=true)]
+    regions: Vec<PathBuf>,
+
+    /// index file path
     #[arg(short, long, value_name="FILE")]
-    regions: PathBuf,
+    output: PathBuf,
+
+    /// simplify regions before indexing them, removing vertices closer than this distance (in metres) to the simplified boundary
+    #[arg(long, value_name="METRES")]
+    simplify: Option<f64>,
+}
+
+#[derive(Args)]
+struct MatchArgs {
+    /// locations.json path (or NDJSON) or glob pattern, - for stdin, can be given several times
+    #[arg(short, long, value_name="FILE", required=true)]
+    locations: Vec<PathBuf>,
 
-    /// output file path
+    /// regions.json path or glob pattern, can be given several times
+    #[arg(short, long, value_name="FILE", required_unless_present="index")]
+    regions: Vec<PathBuf>,
+
+    /// output file path, - for stdout
     #[arg(short, long, value_name="FILE")]
-    output: PathBuf
+    output: PathBuf,
+
+    /// region index file (built by the index subcommand) to use instead of regions files
+    #[arg(long, value_name="FILE", conflicts_with_all=["regions", "simplify", "buffer"])]
+    index: Option<PathBuf>,
+
+    /// assign locations outside every region to the nearest region
+    #[arg(long)]
+    nearest: bool,
+
+    /// maximum distance (in metres) to the nearest region
+    #[arg(long, value_name="METRES", requires="nearest")]
+    max_distance: Option<f64>,
+
+    /// include the distance (in metres) from every matched location to its region boundary
+    #[arg(long)]
+    boundary_distance: bool,
+
+    /// simplify regions before matching, removing vertices closer than this distance (in metres) to the simplified boundary
+    #[arg(long, value_name="METRES")]
+    simplify: Option<f64>,
+
+    /// grow (or shrink, if negative) every region by this distance (in metres), unless the region declares its own buffer
+    #[arg(long, value_name="METRES", allow_negative_numbers=true)]
+    buffer: Option<f64>,
+
+    /// assign every location to at most one region, resolving overlaps by region priority, area and order
+    #[arg(long)]
+    exclusive: bool,
+
+    /// report, for every location, the paths of matched regions in the region hierarchy (built from regions' parents)
+    #[arg(long, conflicts_with_all=["nearest", "exclusive", "boundary_distance"])]
+    hierarchy: bool,
+
+    /// write per-region statistics instead of matched locations (same as the stats subcommand)
+    #[arg(long, conflicts_with_all=["nearest", "exclusive", "boundary_distance", "hierarchy"])]
+    stats: bool,
+
+    /// evaluate all locations at this time (RFC 3339), instead of their own timestamps
+    #[arg(long, value_name="TIME")]
+    at: Option<DateTime<Utc>>,
+
+    /// write enter/exit events of tracks (locations grouped by their track id) instead of matched locations
+    #[arg(long, conflicts_with_all=["nearest", "exclusive", "boundary_distance", "hierarchy", "stats"])]
+    events: bool,
+
+    /// minimum time (in seconds) a track has to stay inside or outside a region for the change to be reported
+    #[arg(long, value_name="SECONDS", requires="events")]
+    dwell: Option<u32>,
+
+    /// write regions which tracks went through between two consecutive points, without any point inside them
+    #[arg(long, conflicts_with_all=["nearest", "exclusive", "boundary_distance", "hierarchy", "stats", "events", "buffer"])]
+    crossings: bool,
+
+    /// keep running, matching again and rewriting the output whenever the regions (or index) or locations files change
+    #[arg(long)]
+    watch: bool,
+}
+
+// Exit codes shared by all the subcommands, invalid command line arguments exit with 2 (reported by clap)
+enum CliError {
+    /// Input files cannot be read or are invalid, exits with 1
+    Input(String),
+    /// Output cannot be written, exits with 3
+    Output(String),
+}
+
+impl CliError {
+    fn message(&self) -> &str {
+        match self {
+            CliError::Input(message) | CliError::Output(message) => message,
+        }
+    }
+
+    fn exit_code(&self) -> ExitCode {
+        match self {
+            CliError::Input(_) => ExitCode::from(1),
+            CliError::Output(_) => ExitCode::from(3),
+        }
+    }
+}
+
+// Path which stands for stdin (for locations) or stdout (for output)
+const STDIO: &str = "-";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    fn from_extension(path: &Path) -> Compression {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst" | "zstd") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    fn from_magic_bytes(bytes: &[u8]) -> Compression {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+// Compressed input is decompressed while it is read, compression is recognised by magic bytes or by the extension
+fn decompressed<R: BufRead + 'static>(mut reader: R, path: &Path) -> io::Result<Box<dyn BufRead>> {
+    let compression = match Compression::from_magic_bytes(reader.fill_buf()?) {
+        Compression::None => Compression::from_extension(path),
+        compression => compression,
+    };
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+        Compression::Zstd => Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?)),
+    })
+}
+
+enum OutputWriter {
+    Plain(Box<dyn Write>),
+    Gzip(GzEncoder<Box<dyn Write>>),
+    Zstd(zstd::Encoder<'static, Box<dyn Write>>),
+}
+
+impl OutputWriter {
+    fn new(output: Box<dyn Write>, compression: Compression) -> io::Result<OutputWriter> {
+        Ok(match compression {
+            Compression::None => OutputWriter::Plain(output),
+            Compression::Gzip => OutputWriter::Gzip(GzEncoder::new(output, flate2::Compression::default())),
+            Compression::Zstd => OutputWriter::Zstd(zstd::Encoder::new(output, zstd::DEFAULT_COMPRESSION_LEVEL)?),
+        })
+    }
+
+    // Compressed streams have to be ended explicitly
+    fn finish(self) -> io::Result<()> {
+        match self {
+            OutputWriter::Plain(mut output) => output.flush(),
+            OutputWriter::Gzip(encoder) => encoder.finish()?.flush(),
+            OutputWriter::Zstd(encoder) => encoder.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for OutputWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            OutputWriter::Plain(output) => output.write(buf),
+            OutputWriter::Gzip(encoder) => encoder.write(buf),
+            OutputWriter::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OutputWriter::Plain(output) => output.flush(),
+            OutputWriter::Gzip(encoder) => encoder.flush(),
+            OutputWriter::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+// Output is compressed when the file name ends with .gz or .zst
+fn write_output<T: Serialize>(path: &Path, value: &T) -> Result<(), CliError> {
+    if path == Path::new(STDIO) {
+        return serialize_output(Box::new(io::stdout().lock()), Compression::None, value, true)
+    }
+    let create_error = |path: &Path, e: io::Error| CliError::Output(format!("Cannot create output file! (tried {}), os: {}", path.display(), e));
+
+    // Regular files are written into a sibling file which then replaces them at once, so the previous output stays intact
+    // when writing fails and readers never see a partially written file. Anything else (e.g. /dev/null or a pipe) is written directly.
+    let replaceable = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata.file_type().is_file(),
+        Err(e) => e.kind() == io::ErrorKind::NotFound,
+    };
+    if !replaceable {
+        let file = File::create(path).map_err(|e| create_error(path, e))?;
+        return serialize_output(Box::new(file), Compression::from_extension(path), value, false)
+    }
+    let mut temp_name = OsString::from(".");
+    temp_name.push(path.file_name().unwrap_or_default());
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = path.with_file_name(temp_name);
+
+    let file = File::create(&temp_path).map_err(|e| create_error(&temp_path, e))?;
+    let result = serialize_output(Box::new(file), Compression::from_extension(path), value, false)
+        .and_then(|()| std::fs::rename(&temp_path, path)
+            .map_err(|e| CliError::Output(format!("Cannot replace the output file {}! os: {}", path.display(), e))));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+fn serialize_output<T: Serialize>(output: Box<dyn Write>, compression: Compression, value: &T, to_stdout: bool) -> Result<(), CliError> {
+    let write_error = |e: &dyn std::error::Error| CliError::Output(format!("An error occurred while writing the output file! {}", e));
+    let mut writer = BufWriter::new(OutputWriter::new(output, compression).map_err(|e| write_error(&e))?);
+    serde_json::to_writer_pretty(&mut writer, value).map_err(|e| write_error(&e))?;
+    if to_stdout {
+        writeln!(writer).map_err(|e| write_error(&e))?;
+    }
+    writer.into_inner()
+        .map_err(|e| write_error(e.error()))?
+        .finish()
+        .map_err(|e| write_error(&e))
+}
+
+fn read_json(path: &Path) -> Result<serde_json::Value, CliError> {
+    File::open(path)
+        .map_err(|e| format!("Input file error! (looked in {}), os: {}", path.display(), e))
+        .and_then(|file| decompressed(BufReader::new(file), path).map_err(|e| format!("An error occurred while reading the input file! {}", e)))
+        .and_then(|reader|
+            serde_json::from_reader(reader)
+                .map_err(|e| format!("An error occurred while reading the input file! {}", e))
+        )
+        .map_err(CliError::Input)
+}
+
+// Locations are either a JSON array, or a stream of location objects (e.g. NDJSON, one location per line)
+fn parse_locations<R: BufRead>(mut reader: R, source: &Path) -> Result<Vec<Location>, String> {
+    let read_error = |e: &dyn std::error::Error| format!("An error occurred while reading the locations file {}! {}", source.display(), e);
+    let is_array = loop {
+        let buffer = reader.fill_buf().map_err(|e| read_error(&e))?;
+        if buffer.is_empty() {
+            break true;
+        }
+        match buffer.iter().position(|byte| !byte.is_ascii_whitespace()) {
+            Some(start) => {
+                let is_array = buffer[start] == b'[';
+                reader.consume(start);
+                break is_array;
+            },
+            None => {
+                let len = buffer.len();
+                reader.consume(len);
+            }
+        }
+    };
+
+    if is_array {
+        serde_json::from_reader(reader).map_err(|e| read_error(&e))
+    } else {
+        serde_json::Deserializer::from_reader(reader).into_iter::<Location>()
+            .collect::<Result<_, _>>()
+            .map_err(|e| read_error(&e))
+    }
+}
+
+fn read_locations_file(path: &Path) -> Result<Vec<Location>, String> {
+    let read_error = |e: io::Error| format!("An error occurred while reading the locations file {}! {}", path.display(), e);
+    if path == Path::new(STDIO) {
+        let stdin = decompressed(io::stdin().lock(), path).map_err(read_error)?;
+        return parse_locations(stdin, Path::new("<stdin>"));
+    }
+    File::open(path)
+        .map_err(|e| format!("Location file error! (looked in {}), os: {}", path.display(), e))
+        .and_then(|locations_file| decompressed(BufReader::new(locations_file), path).map_err(read_error))
+        .and_then(|reader| parse_locations(reader, path))
+}
+
+fn read_regions_file(path: &Path) -> Result<Vec<Region>, String> {
+    let read_error = |e: &dyn std::error::Error| format!("An error occurred while reading the regions file {}! {}", path.display(), e);
+    File::open(path)
+        .map_err(|e| format!("Region file error! (looked in {}), os: {}", path.display(), e))
+        .and_then(|regions_file| decompressed(BufReader::new(regions_file), path).map_err(|e| read_error(&e)))
+        .and_then(|reader| serde_json::from_reader(reader).map_err(|e| read_error(&e)))
+}
+
+// Glob patterns are expanded (into files in alphabetical order), other paths are kept as they are
+fn expand_paths(patterns: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let mut paths = vec![];
+    for pattern in patterns {
+        let pattern_str = pattern.to_string_lossy();
+        if !pattern_str.contains(['*', '?', '[']) {
+            paths.push(pattern.clone());
+            continue;
+        }
+        let matches: Vec<PathBuf> = glob::glob(&pattern_str)
+            .map_err(|e| format!("Invalid glob pattern {}! {}", pattern_str, e))?
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("Cannot read files matching {}! {}", pattern_str, e))?;
+        if matches.is_empty() {
+            return Err(format!("No files match {}", pattern_str));
+        }
+        paths.extend(matches);
+    }
+    Ok(paths)
+}
+
+// Reads and merges all the files, every file is read so errors in all of them are reported at once.
+// `name` gives the name of every item, which is used to find items with the same name in different files.
+fn read_files<T>(patterns: &[PathBuf], read_file: fn(&Path) -> Result<Vec<T>, String>, name: fn(&T) -> &str) -> Result<(Vec<T>, Vec<String>), String> {
+    let paths = expand_paths(patterns)?;
+    let (mut items, mut errors, mut duplicates) = (vec![], vec![], vec![]);
+    let mut sources: HashMap<String, usize> = HashMap::new();
+    for (idx, path) in paths.iter().enumerate() {
+        match read_file(path) {
+            Ok(file_items) => {
+                for item in file_items {
+                    match sources.get(name(&item)) {
+                        Some(&source) if source != idx => {
+                            let duplicate = format!("{} is defined in both {} and {}", name(&item), paths[source].display(), path.display());
+                            if !duplicates.contains(&duplicate) {
+                                duplicates.push(duplicate);
+                            }
+                        },
+                        Some(_) => {},
+                        None => { sources.insert(name(&item).to_owned(), idx); },
+                    }
+                    items.push(item);
+                }
+            },
+            Err(e) => errors.push(e),
+        }
+    }
+    if errors.is_empty() { Ok((items, duplicates)) } else { Err(errors.join("\n")) }
+}
+
+// Locations with the same name in different files are only reported, as shards may repeat them
+fn read_locations(patterns: &[PathBuf]) -> Result<Vec<Location>, String> {
+    let (locations, duplicates) = read_files(patterns, read_locations_file, |loc| &loc.name)?;
+    duplicates.iter().for_each(|duplicate| eprintln!("Warning: location {}", duplicate));
+    Ok(locations)
+}
+
+fn read_regions(patterns: &[PathBuf]) -> Result<Vec<Region>, String> {
+    let (regions, duplicates) = read_files(patterns, read_regions_file, |region| &region.name)?;
+    if !duplicates.is_empty() {
+        let errors: Vec<String> = duplicates.iter().map(|duplicate| format!("Region {}", duplicate)).collect();
+        return Err(errors.join("\n"));
+    }
+    Ok(regions)
+}
+
+// Both inputs are read, so errors in both of them are reported at once
+fn read_inputs(locations: &[PathBuf], regions: &[PathBuf]) -> Result<(Vec<Location>, Vec<Region>), CliError> {
+    match (read_locations(locations), read_regions(regions)) {
+        (Ok(locs), Ok(regs)) => Ok((locs, regs)),
+        (loc_res, reg_res) => {
+            let errors: Vec<String> = [loc_res.err(), reg_res.err()].into_iter().flatten().collect();
+            Err(CliError::Input(errors.join("\n")))
+        }
+    }
 }
 
 fn main() -> ExitCode {
-    let cli = Cli::parse();
-    let locations_result: Result<Vec<Location>, std::string::String> = File::open(&cli.locations)
-        .map_err(|e| format!("Location file error! (looked in {}), os: {}", cli.locations.display(), e))
-        .and_then(|locations_file| 
-            serde_json::from_reader(BufReader::new(locations_file))
-                .map_err(|e| format!("An error occurred while reading the locations file! {}", e))
-        );
-    let regions_result: Result<Vec<Region>, std::string::String> = File::open(&cli.regions)
-        .map_err(|e| format!("Region file error! (looked in {}), os: {}", cli.regions.display(), e))
-        .and_then(|regions_file| 
-            serde_json::from_reader(BufReader::new(regions_file))
-                .map_err(|e| format!("An error occurred while reading the regions file! {}", e))
+    let cli = Cli::parse_from(points_last(std::env::args_os().collect()));
+    let result = match (cli.command, cli.match_args) {
+        (Some(Command::Match(args)), _) | (None, Some(args)) => match_locations(args),
+        (Some(Command::Validate(args)), _) => validate(args),
+        (Some(Command::Convert(args)), _) => convert(args),
+        (Some(Command::Stats(args)), _) => stats(args),
+        (Some(Command::Lookup(args)), _) => lookup(args),
+        (Some(Command::Describe(args)), _) => describe(args),
+        (Some(Command::Analyze(args)), _) => analyze(args),
+        (Some(Command::Combine(args)), _) => combine(args),
+        (Some(Command::Serve(args)), _) => serve(args),
+        (Some(Command::Index(args)), _) => index(args),
+        (Some(Command::Simplify(args)), _) => simplify(args),
+        (None, None) => unreachable!("Match arguments are required without a subcommand"),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e.message());
+            e.exit_code()
+        }
+    }
+}
+
+fn validate(args: ValidateArgs) -> Result<(), CliError> {
+    let regions = read_regions(&args.regions).map_err(CliError::Input)?;
+    let locations = (!args.locations.is_empty()).then(|| read_locations(&args.locations)).transpose().map_err(CliError::Input)?;
+
+    let errors = validate_regions(&regions);
+    if !errors.is_empty() {
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        return Err(CliError::Input(messages.join("\n")));
+    }
+    match locations {
+        Some(locations) => println!("{} regions and {} locations are valid", regions.len(), locations.len()),
+        None => println!("{} regions are valid", regions.len()),
+    }
+    Ok(())
+}
+
+fn convert(args: ConvertArgs) -> Result<(), CliError> {
+    let input = read_json(&args.input)?;
+    let invalid = |kind: &'static str| move |e: serde_json::Error| CliError::Input(format!("An error occurred while reading the {} file! {}", kind, e));
+    // GeoJSON files are feature collection objects, while regions and locations files are arrays
+    let output = match (args.kind, input.is_object()) {
+        (ConvertKind::Regions, true) => serde_json::to_value(regions_from_geojson(input).map_err(CliError::Input)?).unwrap(),
+        (ConvertKind::Locations, true) => serde_json::Value::Array(
+            locations_from_geojson(input).map_err(CliError::Input)?.iter().map(Location::to_json).collect()
+        ),
+        (ConvertKind::Regions, false) => regions_to_geojson(&serde_json::from_value::<Vec<Region>>(input).map_err(invalid("regions"))?),
+        (ConvertKind::Locations, false) => locations_to_geojson(&serde_json::from_value::<Vec<Location>>(input).map_err(invalid("locations"))?),
+    };
+    write_output(&args.output, &output)
+}
+
+fn stats(args: StatsArgs) -> Result<(), CliError> {
+    let (mut locations, mut regions) = read_inputs(&args.locations, &args.regions)?;
+    prepare_inputs(&mut locations, &mut regions, args.at, None, args.buffer);
+    write_output(&args.output, &match_stats(&locations, &regions))
+}
+
+fn lookup(args: LookupArgs) -> Result<(), CliError> {
+    let mut regions = read_regions(&args.regions).map_err(CliError::Input)?;
+    // Regions inactive at the time are neither looked up nor the nearest ones
+    regions.retain(|region| region.is_active_at(args.at.as_ref()));
+
+    for point in &args.points {
+        let found: Vec<String> = lookup_regions(point, &regions, args.at.as_ref()).into_iter()
+            .map(|region| match args.boundary_distance {
+                true => format!("{} ({:.1} m to boundary)", region.name, distance_to_region_boundary(region, point)),
+                false => region.name.clone(),
+            })
+            .collect();
+        // Points outside every region get the distance to the nearest region
+        let nearest = (found.is_empty() && args.boundary_distance).then(|| nearest_region(point, &regions, None)).flatten();
+        let found = match (found.is_empty(), nearest) {
+            (false, _) => found.join(", "),
+            (true, Some(nearest)) => format!("no regions (nearest: {}, {:.1} m to boundary)", nearest.region, nearest.distance),
+            (true, None) => "no regions".to_owned(),
+        };
+        println!("{}, {}: {}", point.latitude.val(), point.longitude.val(), found);
+    }
+    Ok(())
+}
+
+fn print_properties_table(properties: &[RegionProperties]) {
+    let name_width = properties.iter().map(|props| props.region.chars().count()).chain([6]).max().unwrap();
+    println!("{:<name_width$}  {:>14}  {:>14}  {:>22}  {:>45}", "region", "area (km²)", "perimeter (km)", "centroid", "bounding box");
+    for props in properties {
+        let centroid = props.centroid.as_ref()
+            .map_or("-".to_owned(), |c| format!("{:.5}, {:.5}", c.longitude.val(), c.latitude.val()));
+        let bounding_box = props.bounding_box.as_ref()
+            .map_or("-".to_owned(), |bbox| format!(
+                "{:.5}, {:.5} - {:.5}, {:.5}",
+                bbox.min.longitude.val(), bbox.min.latitude.val(), bbox.max.longitude.val(), bbox.max.latitude.val()
+            ));
+        println!("{:<name_width$}  {:>14.3}  {:>14.3}  {:>22}  {:>45}", props.region, props.area_km2, props.perimeter_km, centroid, bounding_box);
+    }
+}
+
+fn describe(args: DescribeArgs) -> Result<(), CliError> {
+    let regions = read_regions(&args.regions).map_err(CliError::Input)?;
+
+    let properties = describe_regions(&regions);
+    match args.format {
+        DescribeFormat::Table => print_properties_table(&properties),
+        DescribeFormat::Json => println!("{}", serde_json::to_string_pretty(&properties).unwrap()),
+    }
+    Ok(())
+}
+
+fn analyze(args: AnalyzeArgs) -> Result<(), CliError> {
+    let regions = read_regions(&args.regions).map_err(CliError::Input)?;
+    let coverage = args.coverage.as_deref().map(read_regions_file).transpose().map_err(CliError::Input)?;
+
+    write_output(&args.output, &analyze_regions(&regions, coverage.as_deref()))
+}
+
+fn combine(args: CombineArgs) -> Result<(), CliError> {
+    let mut regions = read_regions(&args.regions).map_err(CliError::Input)?;
+    let combined = combine_regions_by_name(&args.name, args.operation, &regions, &args.region_names)
+        .map_err(|e| CliError::Input(e.to_string()))?;
+
+    if args.append {
+        regions.push(combined);
+        write_output(&args.output, &regions)
+    } else {
+        write_output(&args.output, &[combined])
+    }
+}
+
+fn print_simplification_reports(reports: &[SimplificationReport]) {
+    for report in reports {
+        eprintln!(
+            "{}: {} -> {} vertices ({:.1}% removed), max deviation {:.2} m",
+            report.region, report.vertices_before, report.vertices_after, report.reduction() * 100.0, report.max_deviation
         );
+    }
+}
 
-    let (locations, regions) = match (locations_result, regions_result) {
-        (Ok(locs), Ok(regs)) => (locs, regs),
-        (loc_res, reg_res) => {
-            let _ = loc_res.inspect_err(|e| println!("{}", e));
-            let _ = reg_res.inspect_err(|e| println!("{}", e));
-            return ExitCode::FAILURE
+fn simplify(args: SimplifyArgs) -> Result<(), CliError> {
+    let mut regions = read_regions(&args.regions).map_err(CliError::Input)?;
+
+    print_simplification_reports(&simplify_regions(&mut regions, args.tolerance));
+    write_output(&args.output, &regions)
+}
+
+// Applies the options which change how locations and regions are matched
+fn prepare_inputs(locations: &mut [Location], regions: &mut [Region], at: Option<DateTime<Utc>>, simplify: Option<f64>, buffer: Option<f64>) {
+    if let Some(at) = at {
+        locations.iter_mut().for_each(|loc| loc.timestamp = Some(at));
+    }
+    if let Some(tolerance) = simplify {
+        print_simplification_reports(&simplify_regions(regions, tolerance));
+    }
+    if let Some(buffer) = buffer {
+        regions.iter_mut().for_each(|region| region.buffer = region.buffer.or(Some(buffer)));
+    }
+}
+
+fn load_index(path: &Path) -> Result<RegionIndex, CliError> {
+    RegionIndex::load(path).map_err(|e| CliError::Input(format!("Cannot load the index file {}! {}", path.display(), e)))
+}
+
+fn serve(args: ServeArgs) -> Result<(), CliError> {
+    let index = match &args.index {
+        Some(path) => load_index(path)?,
+        None => RegionIndex::validated(read_regions(&args.regions).map_err(CliError::Input)?)
+            .map_err(|e| CliError::Input(e.to_string()))?,
+    };
+    let server = MatchServer::bind(&args.address, index)
+        .map_err(|e| CliError::Input(format!("Cannot listen on {}! {}", args.address, e)))?;
+    let workers = args.workers.unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |workers| workers.get()));
+
+    eprintln!("Listening on http://{}", server.local_addr().map_or(args.address.clone(), |address| address.to_string()));
+    server.run(workers);
+    Ok(())
+}
+
+fn index(args: IndexArgs) -> Result<(), CliError> {
+    let mut regions = read_regions(&args.regions).map_err(CliError::Input)?;
+    if let Some(tolerance) = args.simplify {
+        print_simplification_reports(&simplify_regions(&mut regions, tolerance));
+    }
+
+    let errors = validate_regions(&regions);
+    if !errors.is_empty() {
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        return Err(CliError::Input(messages.join("\n")));
+    }
+    RegionIndex::new(regions).write(&args.output)
+        .map_err(|e| CliError::Output(format!("Cannot write the index file {}! {}", args.output.display(), e)))
+}
+
+fn match_locations(args: MatchArgs) -> Result<(), CliError> {
+    if args.watch {
+        return watch_matches(&args)
+    }
+    let index = args.index.as_deref().map(load_index).transpose()?;
+    let (mut locations, mut regions) = match index {
+        Some(_) => (read_locations(&args.locations).map_err(CliError::Input)?, vec![]),
+        None => read_inputs(&args.locations, &args.regions)?,
+    };
+    prepare_inputs(&mut locations, &mut regions, args.at, args.simplify, args.buffer);
+    let regions = index.as_ref().map_or(regions.as_slice(), |index| index.regions());
+    write_matches(&args, &locations, regions, index.as_ref())
+}
+
+fn write_matches(args: &MatchArgs, locations: &[Location], regions: &[Region], index: Option<&RegionIndex>) -> Result<(), CliError> {
+    // The hierarchy is validated whenever regions declare parents, even if the paths are not requested (indexed regions are already validated)
+    if args.hierarchy || (index.is_none() && regions.iter().any(|region| region.parent.is_some())) {
+        let hierarchy = RegionHierarchy::new(regions)
+            .map_err(|e| CliError::Input(format!("Invalid region hierarchy! {}", e)))?;
+        if args.hierarchy {
+            return write_output(&args.output, &hierarchy.match_locations(locations))
         }
+    }
+
+    if args.events {
+        let events = detect_events(locations, regions, args.dwell.map(|secs| TimeDelta::seconds(secs.into())))
+            .map_err(|e| CliError::Input(e.to_string()))?;
+        return write_output(&args.output, &events)
+    }
+
+    if args.crossings {
+        let crossings = detect_crossings(locations, regions)
+            .map_err(|e| CliError::Input(e.to_string()))?;
+        return write_output(&args.output, &crossings)
+    }
+
+    if args.stats {
+        return write_output(&args.output, &match_stats(locations, regions))
+    }
+
+    let mut matched_results = match (index, args.exclusive) {
+        (_, true) => match_locations_to_regions_exclusively(locations, regions),
+        (Some(index), false) => index.match_locations(locations),
+        (None, false) => match_locations_to_regions(locations, regions),
     };
+    if args.nearest {
+        matched_results = add_nearest_locations(matched_results, locations, regions, args.max_distance);
+    }
+
+    if args.boundary_distance {
+        write_output(&args.output, &with_distance_to_boundary(matched_results, regions))
+    } else {
+        write_output(&args.output, &matched_results)
+    }
+}
+
+// How often watched files are checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+// Modification time and size of every file matching the patterns (None if the file cannot be accessed)
+type Fingerprint = Vec<(PathBuf, Option<(SystemTime, u64)>)>;
+
+fn fingerprint(patterns: &[PathBuf]) -> Fingerprint {
+    // Patterns matching no files are reported when the files are read
+    expand_paths(patterns).unwrap_or_default().into_iter()
+        .map(|path| {
+            let metadata = std::fs::metadata(&path).ok().and_then(|metadata| Some((metadata.modified().ok()?, metadata.len())));
+            (path, metadata)
+        })
+        .collect()
+}
+
+// Files which are read again once they changed, but only after they stayed unchanged for a whole interval, so files which are still being saved are not read
+struct WatchedFiles {
+    patterns: Vec<PathBuf>,
+    seen: Fingerprint,
+    read: Option<Fingerprint>,
+}
+
+impl WatchedFiles {
+    fn new(patterns: Vec<PathBuf>) -> WatchedFiles {
+        let seen = fingerprint(&patterns);
+        WatchedFiles { patterns, seen, read: None }
+    }
+
+    // Whether the files have to be read (again), they are considered read afterwards
+    fn poll(&mut self) -> bool {
+        let current = fingerprint(&self.patterns);
+        let stable = current == self.seen;
+        self.seen = current;
+        if !stable || self.read.as_ref() == Some(&self.seen) {
+            return false
+        }
+        self.read = Some(self.seen.clone());
+        true
+    }
+}
+
+// Regions of the watch mode, compiled once for all the matches until their files change
+fn read_watched_regions(args: &MatchArgs) -> Result<RegionIndex, CliError> {
+    if let Some(path) = &args.index {
+        return load_index(path)
+    }
+    let mut regions = read_regions(&args.regions).map_err(CliError::Input)?;
+    prepare_inputs(&mut [], &mut regions, None, args.simplify, args.buffer);
+    RegionIndex::validated(regions).map_err(|e| CliError::Input(e.to_string()))
+}
+
+fn read_watched_locations(args: &MatchArgs) -> Result<Vec<Location>, CliError> {
+    let mut locations = read_locations(&args.locations).map_err(CliError::Input)?;
+    prepare_inputs(&mut locations, &mut [], args.at, None, None);
+    Ok(locations)
+}
+
+// Last good inputs of the watch mode, with the files they are read from
+struct WatchState {
+    regions_files: WatchedFiles,
+    locations_files: WatchedFiles,
+    index: Option<RegionIndex>,
+    locations: Option<Vec<Location>>,
+}
+
+impl WatchState {
+    fn new(args: &MatchArgs) -> WatchState {
+        WatchState {
+            regions_files: WatchedFiles::new(args.index.clone().map_or_else(|| args.regions.clone(), |path| vec![path])),
+            locations_files: WatchedFiles::new(args.locations.clone()),
+            index: None,
+            locations: None,
+        }
+    }
+
+    // Reads the inputs which changed and matches again when any of them was read. Inputs which cannot be read (or are invalid)
+    // are reported, the last good regions and locations are kept and the output is not rewritten until they are fixed.
+    fn update(&mut self, args: &MatchArgs) {
+        let mut changed = false;
+        if self.regions_files.poll() {
+            match read_watched_regions(args) {
+                Ok(index) => {
+                    self.index = Some(index);
+                    changed = true;
+                },
+                Err(e) => eprintln!("{}\nThe output is kept until the regions are fixed", e.message()),
+            }
+        }
+        if self.locations_files.poll() {
+            match read_watched_locations(args) {
+                Ok(locations) => {
+                    self.locations = Some(locations);
+                    changed = true;
+                },
+                Err(e) => eprintln!("{}\nThe output is kept until the locations are fixed", e.message()),
+            }
+        }
+
+        if let (true, Some(index), Some(locations)) = (changed, &self.index, &self.locations) {
+            match write_matches(args, locations, index.regions(), Some(index)) {
+                Ok(()) => eprintln!("Matched {} locations to {} regions, {} written", locations.len(), index.regions().len(), args.output.display()),
+                Err(e) => eprintln!("{}", e.message()),
+            }
+        }
+    }
+}
+
+// Matches whenever the inputs change, until interrupted
+fn watch_matches(args: &MatchArgs) -> Result<(), CliError> {
+    if args.locations.iter().any(|path| path == Path::new(STDIO)) {
+        return Err(CliError::Input("Locations cannot be read from stdin in watch mode".into()))
+    }
+    let mut state = WatchState::new(args);
+    eprintln!("Watching the input files for changes, press Ctrl+C to stop");
+    loop {
+        state.update(args);
+        std::thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_locations() {
+        let array = r#" [{"name": "a", "coordinates": [1.0, 2.0]}, {"name": "b", "coordinates": [3.0, 4.0]}]"#;
+        let ndjson = "\n{\"name\": \"a\", \"coordinates\": [1.0, 2.0]}\n{\"name\": \"b\", \"coordinates\": [3.0, 4.0]}\n";
+
+        let from_array = parse_locations(array.as_bytes(), Path::new("array")).unwrap();
+        assert_eq!(from_array.len(), 2);
+        assert_eq!(parse_locations(ndjson.as_bytes(), Path::new("ndjson")).unwrap(), from_array);
+        assert_eq!(parse_locations("".as_bytes(), Path::new("empty")).map_err(|_| ()), Err(()));
+        assert!(parse_locations("{\"name\": \"a\"}".as_bytes(), Path::new("invalid")).is_err());
+    }
+
+    #[test]
+    fn test_read_regions_from_several_files() {
+        let dir = std::env::temp_dir().join(format!("regions_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let region = |name: &str| format!(r#"{{"name": "{}", "coordinates": [[[0, 0], [1, 0], [1, 1], [0, 0]]]}}"#, name);
+        std::fs::write(dir.join("a.json"), format!("[{}, {}]", region("a"), region("b"))).unwrap();
+        std::fs::write(dir.join("b.json"), format!("[{}]", region("c"))).unwrap();
+        std::fs::write(dir.join("c.json"), format!("[{}]", region("a"))).unwrap();
+
+        let regions = read_regions(&[dir.join("[ab].json")]).unwrap();
+        assert_eq!(regions.iter().map(|region| region.name.as_str()).collect::<Vec<_>>(), vec!["a", "b", "c"]);
+
+        let duplicate = read_regions(&[dir.join("*.json")]).unwrap_err();
+        assert!(duplicate.starts_with("Region a is defined in both") && duplicate.ends_with("c.json"), "{}", duplicate);
+        assert!(read_regions(&[dir.join("*.geojson")]).unwrap_err().starts_with("No files match"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_compressed_files() {
+        let dir = std::env::temp_dir().join(format!("compressed_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let locations = parse_locations(r#"[{"name": "a", "coordinates": [1.0, 2.0]}]"#.as_bytes(), Path::new("array")).unwrap();
+        let json: Vec<serde_json::Value> = locations.iter().map(Location::to_json).collect();
+
+        for name in ["locations.json.gz", "locations.json.zst"] {
+            write_output(&dir.join(name), &json).map_err(|e| e.message().to_owned()).unwrap();
+            assert_eq!(read_locations_file(&dir.join(name)).unwrap(), locations);
+            // Compression is recognised by magic bytes as well
+            std::fs::rename(dir.join(name), dir.join("locations.json")).unwrap();
+            assert_eq!(read_locations_file(&dir.join("locations.json")).unwrap(), locations);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_watched_files() {
+        let path = std::env::temp_dir().join(format!("watched_{}.json", std::process::id()));
+        std::fs::write(&path, "[]").unwrap();
+        let mut files = WatchedFiles::new(vec![path.clone()]);
+        assert!(files.poll());
+        assert!(!files.poll());
+
+        // A changed file is only read once it stops changing
+        std::fs::write(&path, "[{}]").unwrap();
+        assert!(!files.poll());
+        assert!(files.poll());
+        assert!(!files.poll());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(!files.poll());
+        assert!(files.poll());
+    }
+
+    #[test]
+    fn test_lookup_points_with_negative_latitude() {
+        let parse = |args: &[&str]| match Cli::parse_from(points_last(args.iter().map(OsString::from).collect())).command {
+            Some(Command::Lookup(args)) => (args.points.iter().map(|point| point.latitude.val()).collect::<Vec<_>>(), args.regions),
+            _ => panic!("Not a lookup"),
+        };
+        let expected = (vec![-33.92, 54.68], vec![PathBuf::from("regions.json")]);
+        assert_eq!(parse(&["tt", "lookup", "-33.92,18.42", "54.68,25.27", "-r", "regions.json"]), expected);
+        assert_eq!(parse(&["tt", "lookup", "-r", "regions.json", "-33.92,18.42", "54.68,25.27"]), expected);
+        assert_eq!(parse(&["tt", "lookup", "-33.92,18.42", "-r", "regions.json", "--", "54.68,25.27"]), expected);
+    }
+
+    #[test]
+    fn test_failed_write_keeps_previous_output() {
+        let path = std::env::temp_dir().join(format!("output_{}.json", std::process::id()));
+        assert!(write_output(&path, &vec!["first"]).is_ok());
+
+        // JSON object keys have to be strings
+        let invalid: HashMap<(u8, u8), u8> = HashMap::from([((1, 2), 3)]);
+        assert!(write_output(&path, &invalid).is_err());
+        assert_eq!(serde_json::from_str::<Vec<String>>(&std::fs::read_to_string(&path).unwrap()).unwrap(), vec!["first"]);
+        let leftovers = std::fs::read_dir(std::env::temp_dir()).unwrap()
+            .filter(|entry| entry.as_ref().unwrap().file_name().to_string_lossy().starts_with(&format!(".output_{}", std::process::id())))
+            .count();
+        assert_eq!(leftovers, 0);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_watch_keeps_last_good_output() {
+        let dir = std::env::temp_dir().join(format!("watch_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (regions, locations, output) = (dir.join("regions.json"), dir.join("locations.json"), dir.join("output.json"));
+        std::fs::write(&regions, r#"[{"name": "a", "coordinates": [[[0, 0], [2, 0], [2, 2], [0, 2], [0, 0]]]}]"#).unwrap();
+        std::fs::write(&locations, r#"[{"name": "p", "coordinates": [1, 1]}]"#).unwrap();
+        let args = match Cli::parse_from(["tt", "match", "--watch", "-l", locations.to_str().unwrap(), "-r", regions.to_str().unwrap(), "-o", output.to_str().unwrap()]).command {
+            Some(Command::Match(args)) => args,
+            _ => panic!("Not a match"),
+        };
+
+        let mut state = WatchState::new(&args);
+        state.update(&args);
+        let written = std::fs::read_to_string(&output).unwrap();
+        assert!(written.contains("\"p\""), "{}", written);
 
-    let matched_results = match_locations_to_regions(&locations, &regions);
+        // The ring is not closed, the edit is only read once the file stops changing
+        std::fs::write(&regions, r#"[{"name": "b", "coordinates": [[[0, 0], [2, 0], [2, 2], [0, 2], [1, 1]]]}]"#).unwrap();
+        state.update(&args);
+        state.update(&args);
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), written);
 
-    let file = File::create(&cli.output)
-        .expect("Cannot create output file!");
-    let mut writer = BufWriter::new(file);
+        // Locations are matched against the last good regions
+        std::fs::write(&locations, r#"[{"name": "q", "coordinates": [1, 1]}]"#).unwrap();
+        state.update(&args);
+        state.update(&args);
+        let rewritten = std::fs::read_to_string(&output).unwrap();
+        assert!(rewritten.contains("\"a\"") && rewritten.contains("\"q\""), "{}", rewritten);
 
-    serde_json::to_writer_pretty(&mut writer, &matched_results).unwrap();
-    writer.flush().unwrap();
-    ExitCode::SUCCESS
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
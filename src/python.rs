@@ -0,0 +1,183 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use numpy::PyReadonlyArray1;
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+use crate::{data_structures::{Coordinates, DecimalLat, DecimalLon, Location}, index::RegionIndex};
+
+// Regions and locations cross the boundary as JSON, in the same format as the files of the CLI
+fn to_json(value: &Bound<'_, PyAny>) -> PyResult<String> {
+    match value.extract::<String>() {
+        Ok(json) => Ok(json),
+        Err(_) => value.py().import("json")?.call_method1("dumps", (value,))?.extract(),
+    }
+}
+
+fn from_json<'py>(py: Python<'py>, json: &str) -> PyResult<Bound<'py, PyAny>> {
+    py.import("json")?.call_method1("loads", (json,))
+}
+
+fn parse_time(at: Option<&str>) -> PyResult<Option<DateTime<Utc>>> {
+    at.map(|at| at.parse::<DateTime<Utc>>().map_err(|e| PyValueError::new_err(format!("Invalid time {}: {}", at, e))))
+        .transpose()
+}
+
+/// Regions compiled into an index, built from a JSON string or a list of dicts in the regions file format.
+#[pyclass(name = "Regions", frozen)]
+pub struct PyRegions {
+    index: RegionIndex,
+}
+
+#[pymethods]
+impl PyRegions {
+    #[new]
+    fn new(regions: &Bound<'_, PyAny>) -> PyResult<PyRegions> {
+        let index = RegionIndex::from_json(to_json(regions)?.as_bytes()).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(PyRegions { index })
+    }
+
+    /// Names of the regions, region indices returned by `lookup` point into this list.
+    #[getter]
+    fn names(&self) -> Vec<String> {
+        self.index.regions().iter().map(|region| region.name.clone()).collect()
+    }
+
+    fn __len__(&self) -> usize {
+        self.index.regions().len()
+    }
+
+    /// Indices of the regions containing every point of the NumPy arrays of latitudes and longitudes,
+    /// optionally only of regions active at the `at` time (RFC 3339).
+    #[pyo3(signature = (lat, lon, at=None))]
+    fn lookup(&self, py: Python<'_>, lat: PyReadonlyArray1<'_, f64>, lon: PyReadonlyArray1<'_, f64>, at: Option<&str>) -> PyResult<Vec<Vec<usize>>> {
+        let (lat, lon) = (lat.as_array(), lon.as_array());
+        if lat.len() != lon.len() {
+            return Err(PyValueError::new_err(format!("{} latitudes and {} longitudes were given", lat.len(), lon.len())));
+        }
+        let time = parse_time(at)?;
+        let points = lat.iter().zip(lon.iter()).enumerate()
+            .map(|(idx, (&lat, &lon))| match (DecimalLat::new(lat), DecimalLon::new(lon)) {
+                (Ok(latitude), Ok(longitude)) => Ok(Coordinates { longitude, latitude }),
+                (Err(e), _) => Err(PyValueError::new_err(format!("Point {}: {}", idx, e))),
+                (_, Err(e)) => Err(PyValueError::new_err(format!("Point {}: {}", idx, e))),
+            })
+            .collect::<PyResult<Vec<Coordinates>>>()?;
+
+        Ok(py.detach(|| points.iter().map(|point| self.index.lookup_indices(point, time.as_ref())).collect()))
+    }
+}
+
+/// Reads a regions file (JSON in the regions file format).
+#[pyfunction]
+fn load_regions(path: PathBuf) -> PyResult<PyRegions> {
+    RegionIndex::from_json(&std::fs::read(&path)?)
+        .map(|index| PyRegions { index })
+        .map_err(|e| PyValueError::new_err(format!("Invalid regions file {}! {}", path.display(), e)))
+}
+
+/// Reads a region index file built by the index subcommand of the CLI.
+#[pyfunction]
+fn load_index(path: PathBuf) -> PyResult<PyRegions> {
+    RegionIndex::load(&path)
+        .map(|index| PyRegions { index })
+        .map_err(|e| PyValueError::new_err(format!("Cannot load the index file {}! {}", path.display(), e)))
+}
+
+/// Matches locations (a JSON string or a list of dicts in the locations file format) to the regions, optionally evaluating
+/// all of them at the `at` time (RFC 3339, like `--at`), the result is a list of dicts in the output file format of the CLI.
+#[pyfunction(name = "match_locations_to_regions", signature = (locations, regions, at=None))]
+fn py_match_locations_to_regions<'py>(
+    py: Python<'py>,
+    locations: &Bound<'py, PyAny>,
+    regions: &PyRegions,
+    at: Option<&str>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let mut locations: Vec<Location> = serde_json::from_str(&to_json(locations)?)
+        .map_err(|e| PyValueError::new_err(format!("Invalid locations! {}", e)))?;
+    if let Some(at) = parse_time(at)? {
+        locations.iter_mut().for_each(|loc| loc.timestamp = Some(at));
+    }
+    let matched = py.detach(|| serde_json::to_string(&regions.index.match_locations(&locations)))
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    from_json(py, &matched)
+}
+
+#[pymodule]
+fn traveltime_internship_task(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyRegions>()?;
+    module.add_function(wrap_pyfunction!(load_regions, module)?)?;
+    module.add_function(wrap_pyfunction!(load_index, module)?)?;
+    module.add_function(wrap_pyfunction!(py_match_locations_to_regions, module)?)?;
+    Ok(())
+}
+
+// Run with `cargo test --features python`, the tests need Python with NumPy installed
+#[cfg(test)]
+mod tests {
+    use numpy::{PyArray1, PyArrayMethods};
+
+    use super::*;
+
+    const REGIONS: &str = r#"[
+        {"name": "big", "coordinates": [[[0, 0], [2, 0], [2, 2], [0, 2], [0, 0]]]},
+        {"name": "small", "coordinates": [[[0, 0], [1, 0], [1, 1], [0, 1], [0, 0]]], "valid_to": "2024-07-01T00:00:00Z"}
+    ]"#;
+
+    // NumPy arrays cannot be created without NumPy installed in the linked Python, such tests are skipped then
+    fn numpy_missing(py: Python) -> bool {
+        let missing = py.import("numpy").is_err();
+        if missing {
+            eprintln!("NumPy is not installed, skipping the test");
+        }
+        missing
+    }
+
+    #[test]
+    fn test_lookup() {
+        Python::initialize();
+        Python::attach(|py| {
+            if numpy_missing(py) {
+                return
+            }
+            let regions = PyRegions::new(&REGIONS.into_pyobject(py).unwrap()).unwrap();
+            let lat = PyArray1::from_slice(py, &[0.5, 1.5, 3.0]).readonly();
+            let lon = PyArray1::from_slice(py, &[0.5, 1.5, 3.0]).readonly();
+            assert_eq!(regions.lookup(py, lat, lon, None).unwrap(), vec![vec![0, 1], vec![0], vec![]]);
+
+            let lat = PyArray1::from_slice(py, &[0.5]).readonly();
+            let lon = PyArray1::from_slice(py, &[0.5]).readonly();
+            assert_eq!(regions.lookup(py, lat, lon, Some("2024-08-01T00:00:00Z")).unwrap(), vec![vec![0]]);
+        });
+    }
+
+    #[test]
+    fn test_lookup_with_mismatched_lengths() {
+        Python::initialize();
+        Python::attach(|py| {
+            if numpy_missing(py) {
+                return
+            }
+            let regions = PyRegions::new(&REGIONS.into_pyobject(py).unwrap()).unwrap();
+            let lat = PyArray1::from_slice(py, &[0.5, 1.5]).readonly();
+            let lon = PyArray1::from_slice(py, &[0.5]).readonly();
+            let err = regions.lookup(py, lat, lon, None).unwrap_err();
+            assert!(err.is_instance_of::<PyValueError>(py));
+            assert_eq!(err.value(py).to_string(), "2 latitudes and 1 longitudes were given");
+        });
+    }
+
+    #[test]
+    fn test_match_locations_at() {
+        Python::initialize();
+        Python::attach(|py| {
+            let regions = PyRegions::new(&REGIONS.into_pyobject(py).unwrap()).unwrap();
+            let locations = r#"[{"name": "a", "coordinates": [0.5, 0.5]}]"#.into_pyobject(py).unwrap();
+            let matched = py_match_locations_to_regions(py, &locations, &regions, Some("2024-08-01T00:00:00Z")).unwrap();
+            assert_eq!(
+                to_json(&matched).unwrap(),
+                r#"[{"region": "big", "matched_locations": ["a"]}, {"region": "small", "matched_locations": []}]"#
+            );
+        });
+    }
+}
@@ -0,0 +1,199 @@
+use std::{collections::HashMap, io, net::SocketAddr};
+
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response};
+
+use crate::{data_structures::{Coordinates, DecimalLat, DecimalLon, Location}, index::RegionIndex};
+
+/// HTTP service answering matching requests from regions loaded once:
+/// - `POST /match` with a JSON array of locations, responds with the matched results,
+/// - `GET /lookup?lat=&lon=[&at=]`, responds with the names of regions containing the point,
+/// - `GET /regions`, responds with all the regions, without their geometry.
+///
+/// Errors are responded with `{"error": "..."}`.
+pub struct MatchServer {
+    http: tiny_http::Server,
+    index: RegionIndex,
+    // Response of GET /regions, which never changes
+    regions: String,
+}
+
+// Decodes %XX escapes and + (space) of a query string value
+fn decode_query_value(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let escaped = bytes.get(idx + 1..idx + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[idx], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                idx += 3;
+                continue;
+            },
+            (b'+', _) => decoded.push(b' '),
+            (byte, _) => decoded.push(byte),
+        }
+        idx += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn query_params(query: &str) -> HashMap<String, String> {
+    query.split('&')
+        .filter(|param| !param.is_empty())
+        .map(|param| {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            (decode_query_value(key), decode_query_value(value))
+        })
+        .collect()
+}
+
+fn bad_request(message: impl std::fmt::Display) -> (u16, String) {
+    (400, json!({ "error": message.to_string() }).to_string())
+}
+
+impl MatchServer {
+    /// Starts listening on the address (e.g. `127.0.0.1:8080`, port 0 picks a free port), requests are only answered by [`MatchServer::run`].
+    pub fn bind(address: &str, index: RegionIndex) -> io::Result<MatchServer> {
+        let http = tiny_http::Server::http(address).map_err(io::Error::other)?;
+        let regions = Value::Array(index.regions().iter()
+            .map(|region| {
+                let mut summary = serde_json::to_value(region).unwrap();
+                if let Value::Object(summary) = &mut summary {
+                    summary.remove("coordinates");
+                    summary.remove("holes");
+                }
+                summary
+            })
+            .collect()).to_string();
+        Ok(MatchServer { http, index, regions })
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.http.server_addr().to_ip()
+    }
+
+    /// Answers requests with the given number of worker threads, it blocks for as long as the server runs.
+    pub fn run(&self, workers: usize) {
+        std::thread::scope(|scope| {
+            for _ in 0..workers.max(1) {
+                scope.spawn(|| {
+                    for mut request in self.http.incoming_requests() {
+                        let (status, body) = self.handle(&mut request);
+                        let response = Response::from_string(body)
+                            .with_status_code(status)
+                            .with_header(Header::from_bytes("Content-Type", "application/json").unwrap());
+                        // The client may have gone away already, there is nobody to report that to
+                        let _ = request.respond(response);
+                    }
+                });
+            }
+        });
+    }
+
+    fn handle(&self, request: &mut Request) -> (u16, String) {
+        let url = request.url().to_owned();
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+        match (request.method(), path) {
+            (Method::Post, "/match") => {
+                let mut body = String::new();
+                if let Err(e) = request.as_reader().read_to_string(&mut body) {
+                    return bad_request(e);
+                }
+                match serde_json::from_str::<Vec<Location>>(&body) {
+                    Ok(locations) => (200, serde_json::to_string(&self.index.match_locations(&locations)).unwrap()),
+                    Err(e) => bad_request(format!("Invalid locations! {}", e)),
+                }
+            },
+            (Method::Get, "/lookup") => self.lookup(&query_params(query)),
+            (Method::Get, "/regions") => (200, self.regions.clone()),
+            (_, "/match" | "/lookup" | "/regions") => (405, json!({ "error": format!("{} is not allowed for {}", request.method(), path) }).to_string()),
+            _ => (404, json!({ "error": format!("{} does not exist", path) }).to_string()),
+        }
+    }
+
+    fn lookup(&self, params: &HashMap<String, String>) -> (u16, String) {
+        let parse = |name: &str| params.get(name)
+            .ok_or_else(|| format!("Missing {} parameter", name))
+            .and_then(|value| value.parse::<f64>().map_err(|e| format!("Invalid {} parameter: {}", name, e)));
+        let coordinates = match (parse("lat"), parse("lon")) {
+            (Ok(lat), Ok(lon)) => match (DecimalLat::new(lat), DecimalLon::new(lon)) {
+                (Ok(latitude), Ok(longitude)) => Coordinates { longitude, latitude },
+                (Err(e), _) => return bad_request(e),
+                (_, Err(e)) => return bad_request(e),
+            },
+            (Err(e), _) | (_, Err(e)) => return bad_request(e),
+        };
+        let time = match params.get("at").map(|at| at.parse::<DateTime<Utc>>()).transpose() {
+            Ok(time) => time,
+            Err(e) => return bad_request(format!("Invalid at parameter: {}", e)),
+        };
+
+        let regions: Vec<&str> = self.index.lookup(&coordinates, time.as_ref()).iter().map(|region| region.name.as_str()).collect();
+        (200, json!({ "regions": regions }).to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::{Read, Write}, net::TcpStream, sync::Arc};
+
+    use crate::test_utils::{region, square};
+
+    use super::*;
+
+    fn start_server() -> SocketAddr {
+        let index = RegionIndex::new(vec![region("big", square(0.0, 2.0)), region("small", square(0.0, 1.0))]);
+        let server = Arc::new(MatchServer::bind("127.0.0.1:0", index).unwrap());
+        let address = server.local_addr().unwrap();
+        std::thread::spawn(move || server.run(2));
+        address
+    }
+
+    // Sends a request and returns the status code and the parsed body of the response
+    fn request(address: SocketAddr, method: &str, path: &str, body: &str) -> (u16, Value) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}", method, path, body.len(), body).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        let status = response.split(' ').nth(1).unwrap().parse().unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    #[test]
+    fn test_match_endpoint() {
+        let address = start_server();
+        let locations = r#"[{"name": "a", "coordinates": [0.5, 0.5]}, {"name": "b", "coordinates": [1.5, 1.5]}]"#;
+
+        let (status, body) = request(address, "POST", "/match", locations);
+        assert_eq!(status, 200);
+        assert_eq!(body, json!([
+            { "region": "big", "matched_locations": ["a", "b"] },
+            { "region": "small", "matched_locations": ["a"] },
+        ]));
+
+        let (status, body) = request(address, "POST", "/match", "[{\"name\": \"a\"}]");
+        assert_eq!(status, 400);
+        assert!(body["error"].as_str().unwrap().starts_with("Invalid locations!"));
+    }
+
+    #[test]
+    fn test_lookup_and_regions_endpoints() {
+        let address = start_server();
+
+        assert_eq!(request(address, "GET", "/lookup?lat=0.5&lon=1.5", ""), (200, json!({ "regions": ["big"] })));
+        assert_eq!(request(address, "GET", "/lookup?lat=0.5&lon=0.5&at=2024-07-01T08%3A00%3A00Z", ""), (200, json!({ "regions": ["big", "small"] })));
+        assert_eq!(request(address, "GET", "/lookup?lat=95&lon=0.5", "").0, 400);
+        assert_eq!(request(address, "GET", "/lookup?lon=0.5", "").0, 400);
+
+        assert_eq!(request(address, "GET", "/regions", ""), (200, json!([{ "name": "big" }, { "name": "small" }])));
+        assert_eq!(request(address, "DELETE", "/regions", "").0, 405);
+        assert_eq!(request(address, "GET", "/unknown", "").0, 404);
+    }
+}
@@ -0,0 +1,118 @@
+use std::{error::Error, str::FromStr};
+
+use geo::BooleanOps;
+
+use crate::{create_region_from_multipolygon, data_structures::Region, region_union};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetOperation {
+    /// Area covered by any of the regions
+    Union,
+    /// Area covered by all of the regions
+    Intersection,
+    /// Area of the first region not covered by any of the others
+    Difference,
+}
+
+impl FromStr for SetOperation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "union" => Ok(SetOperation::Union),
+            "intersection" => Ok(SetOperation::Intersection),
+            "difference" => Ok(SetOperation::Difference),
+            _ => Err(format!("Unknown operation: {} (it should be one of: union, intersection, difference)", s)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SetOperationError {
+    NoRegions,
+    UnknownRegion(String),
+}
+
+impl Error for SetOperationError {}
+
+impl std::fmt::Display for SetOperationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SetOperationError::NoRegions => write!(f, "At least one region is required"),
+            SetOperationError::UnknownRegion(region) => write!(f, "Region {} does not exist", region),
+        }
+    }
+}
+
+/// Builds a new region from the given ones, every region's polygons (with its holes cut out) are treated as one multipolygon.
+pub fn combine_regions(name: &str, operation: SetOperation, regions: &[&Region]) -> Result<Region, SetOperationError> {
+    let (first, rest) = regions.split_first().ok_or(SetOperationError::NoRegions)?;
+    let rest = rest.iter().map(|region| region_union(region));
+    let combined = match operation {
+        SetOperation::Union => rest.fold(region_union(first), |acc, geometry| acc.union(&geometry)),
+        SetOperation::Intersection => rest.fold(region_union(first), |acc, geometry| acc.intersection(&geometry)),
+        SetOperation::Difference => rest.fold(region_union(first), |acc, geometry| acc.difference(&geometry)),
+    };
+    Ok(create_region_from_multipolygon(name.into(), &combined))
+}
+
+/// Same as [`combine_regions`], but the regions are looked up by their names (the first region with the name is used).
+pub fn combine_regions_by_name(name: &str, operation: SetOperation, regions: &[Region], names: &[String]) -> Result<Region, SetOperationError> {
+    let selected = names.iter()
+        .map(|name| regions.iter().find(|region| &region.name == name).ok_or_else(|| SetOperationError::UnknownRegion(name.clone())))
+        .collect::<Result<Vec<&Region>, SetOperationError>>()?;
+    combine_regions(name, operation, &selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{data_structures::Location, region_area, test_utils::{coords, rectangle, region}, CompiledRegion};
+
+    use super::*;
+
+    fn square(name: &str, min: f64, max: f64) -> Region {
+        region(name, rectangle(min, min, max, max))
+    }
+
+    fn location(longitude: f64, latitude: f64) -> Location {
+        Location { name: "location".into(), coordinates: coords(longitude, latitude), ..Default::default() }
+    }
+
+    #[test]
+    fn test_union_and_intersection() {
+        let (a, b) = (square("a", 0.0, 2.0), square("b", 1.0, 3.0));
+
+        let union = combine_regions("union", SetOperation::Union, &[&a, &b]).unwrap();
+        assert_eq!(union.name, "union");
+        assert_eq!(union.polygons.len(), 1);
+        assert!(CompiledRegion::new(&union).matches(&location(0.5, 0.5)));
+        assert!(CompiledRegion::new(&union).matches(&location(2.5, 2.5)));
+
+        let intersection = combine_regions("intersection", SetOperation::Intersection, &[&a, &b]).unwrap();
+        assert!(CompiledRegion::new(&intersection).matches(&location(1.5, 1.5)));
+        assert!(!CompiledRegion::new(&intersection).matches(&location(0.5, 0.5)));
+        assert!((region_area(&intersection) - region_area(&square("c", 1.0, 2.0))).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_difference_with_hole() {
+        let (outer, exclusion) = (square("outer", 0.0, 3.0), square("exclusion", 1.0, 2.0));
+        let difference = combine_regions("outer_without_exclusion", SetOperation::Difference, &[&outer, &exclusion]).unwrap();
+
+        assert_eq!(difference.holes.len(), 1);
+        assert!(CompiledRegion::new(&difference).matches(&location(0.5, 0.5)));
+        assert!(!CompiledRegion::new(&difference).matches(&location(1.5, 1.5)));
+
+        // The hole survives writing and reading the region back
+        let json = serde_json::to_string(&difference).unwrap();
+        let read_back: Region = serde_json::from_str(&json).unwrap();
+        assert!(!CompiledRegion::new(&read_back).matches(&location(1.5, 1.5)));
+    }
+
+    #[test]
+    fn test_unknown_region() {
+        let regions = vec![square("a", 0.0, 1.0)];
+        let result = combine_regions_by_name("new", SetOperation::Union, &regions, &["a".into(), "b".into()]);
+        assert_eq!(result.unwrap_err(), SetOperationError::UnknownRegion("b".into()));
+    }
+}
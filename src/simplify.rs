@@ -0,0 +1,187 @@
+use std::{collections::{HashMap, HashSet}, f64::consts::PI};
+
+use geo::{coord, EuclideanDistance, SimplifyIdx};
+use serde::Serialize;
+
+use crate::data_structures::{Coordinates, Polygon, Region};
+
+const METRES_PER_DEGREE: f64 = 6_371_008.8 * PI / 180.0;
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct SimplificationReport {
+    pub region: String,
+    pub vertices_before: usize,
+    pub vertices_after: usize,
+    /// Largest distance (in metres) between a removed vertex and the edge which replaced it
+    pub max_deviation: f64,
+}
+
+impl SimplificationReport {
+    /// Fraction of the vertices which were removed
+    pub fn reduction(&self) -> f64 {
+        if self.vertices_before == 0 { 0.0 } else { 1.0 - self.vertices_after as f64 / self.vertices_before as f64 }
+    }
+}
+
+// Local equirectangular projection (in metres) around the ring's mean latitude, precise enough at the scale of a tolerance
+fn project(vertices: &[Coordinates]) -> geo::LineString {
+    let mean_lat = vertices.iter().map(|vert| vert.latitude.val()).sum::<f64>() / vertices.len() as f64;
+    let metres_per_lon_degree = METRES_PER_DEGREE * mean_lat.to_radians().cos();
+    geo::LineString::new(
+        vertices.iter()
+            .map(|vert| coord! { x: vert.longitude.val() * metres_per_lon_degree, y: vert.latitude.val() * METRES_PER_DEGREE })
+            .collect()
+    )
+}
+
+// Vertices are compared exactly, as regions sharing an edge are usually exported with the same coordinates
+type VertexKey = (u64, u64);
+
+fn vertex_key(vertex: &Coordinates) -> VertexKey {
+    (vertex.longitude.val().to_bits(), vertex.latitude.val().to_bits())
+}
+
+// Vertices of more than one ring, e.g. of a parent and a child region sharing part of their boundary
+fn shared_vertices(regions: &[Region]) -> HashSet<VertexKey> {
+    let mut rings_per_vertex: HashMap<VertexKey, usize> = HashMap::new();
+    for poly in regions.iter().flat_map(|region| region.polygons.iter().chain(&region.holes)) {
+        let ring: HashSet<VertexKey> = poly.vertices.iter().map(vertex_key).collect();
+        ring.into_iter().for_each(|key| *rings_per_vertex.entry(key).or_default() += 1);
+    }
+    rings_per_vertex.into_iter().filter(|&(_, rings)| rings > 1).map(|(key, _)| key).collect()
+}
+
+// Returns the largest deviation introduced, rings which would collapse below a triangle are left untouched.
+// Anchors are always kept and the parts of the ring between them are simplified separately, so a boundary shared by
+// several rings stays the same in all of them.
+fn simplify_polygon(polygon: &mut Polygon, tolerance: f64, anchors: &HashSet<VertexKey>) -> f64 {
+    let projected = project(&polygon.vertices);
+    let last = polygon.vertices.len().saturating_sub(1);
+    let mut splits: Vec<usize> = polygon.vertices.iter().enumerate()
+        .filter(|&(idx, vert)| idx == 0 || idx == last || anchors.contains(&vertex_key(vert)))
+        .map(|(idx, _)| idx)
+        .collect();
+    splits.dedup();
+    let mut kept = vec![0];
+    for pair in splits.windows(2) {
+        let part = geo::LineString::new(projected.0[pair[0]..=pair[1]].to_vec());
+        kept.extend(part.simplify_idx(&tolerance).into_iter().skip(1).map(|idx| pair[0] + idx));
+    }
+    if kept.len() < 4 {
+        return 0.0;
+    }
+
+    let points = &projected.0;
+    let deviation = kept.windows(2)
+        .flat_map(|pair| {
+            let edge = geo::Line::new(points[pair[0]], points[pair[1]]);
+            (pair[0] + 1..pair[1]).map(move |idx| geo::Point::from(points[idx]).euclidean_distance(&edge))
+        })
+        .fold(0.0, f64::max);
+    let vertices = kept.iter().map(|&idx| polygon.vertices[idx].clone()).collect();
+    polygon.vertices = vertices;
+    deviation
+}
+
+/// Simplifies every polygon and hole of the region with the Douglas–Peucker algorithm,
+/// removing vertices which lie closer than `tolerance` (in metres) to the simplified boundary.
+pub fn simplify_region(region: &mut Region, tolerance: f64) -> SimplificationReport {
+    let anchors = shared_vertices(std::slice::from_ref(region));
+    simplify_region_with_anchors(region, tolerance, &anchors)
+}
+
+fn simplify_region_with_anchors(region: &mut Region, tolerance: f64, anchors: &HashSet<VertexKey>) -> SimplificationReport {
+    let count_vertices = |region: &Region| region.polygons.iter().chain(&region.holes).map(|poly| poly.vertices.len()).sum();
+    let vertices_before = count_vertices(region);
+    let max_deviation = region.polygons.iter_mut().chain(&mut region.holes)
+        .map(|poly| simplify_polygon(poly, tolerance, anchors))
+        .fold(0.0, f64::max);
+
+    SimplificationReport {
+        region: region.name.clone(),
+        vertices_before,
+        vertices_after: count_vertices(region),
+        max_deviation,
+    }
+}
+
+/// Same as [`simplify_region`] for every region. Vertices shared between rings (of the same or different regions) are kept,
+/// so regions sharing an edge, like a parent and its child, are simplified the same way along it and stay nested.
+pub fn simplify_regions(regions: &mut [Region], tolerance: f64) -> Vec<SimplificationReport> {
+    let anchors = shared_vertices(regions);
+    regions.iter_mut().map(|region| simplify_region_with_anchors(region, tolerance, &anchors)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::coords;
+
+    use super::*;
+
+    // Square with a bottom edge zigzagging ~1.1 m (0.00001°) around the equator
+    fn jagged_square() -> Region {
+        let mut vertices: Vec<Coordinates> = (0..=100)
+            .map(|step| coords(step as f64 / 100.0, if step % 2 == 0 { 0.0 } else { 0.00001 }))
+            .collect();
+        vertices.extend([coords(1.0, 1.0), coords(0.0, 1.0), coords(0.0, 0.0)]);
+        Region {
+            name: "jagged".into(),
+            polygons: vec![Polygon { vertices }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_simplify_region() {
+        let mut region = jagged_square();
+        let report = simplify_region(&mut region, 10.0);
+
+        assert_eq!(report.vertices_before, 104);
+        assert_eq!(report.vertices_after, 5);
+        assert_eq!(region.polygons[0].vertices, vec![coords(0.0, 0.0), coords(1.0, 0.0), coords(1.0, 1.0), coords(0.0, 1.0), coords(0.0, 0.0)]);
+        assert!((report.max_deviation - 1.11).abs() < 0.01, "{}", report.max_deviation);
+        assert!(report.reduction() > 0.95);
+    }
+
+    #[test]
+    fn test_tolerance_below_detail() {
+        let mut region = jagged_square();
+        let report = simplify_region(&mut region, 0.5);
+
+        assert_eq!(report.vertices_after, report.vertices_before);
+        assert_eq!(report.max_deviation, 0.0);
+    }
+
+    #[test]
+    fn test_small_ring_is_kept() {
+        let vertices = vec![coords(0.0, 0.0), coords(0.0001, 0.0), coords(0.0001, 0.0001), coords(0.0, 0.0)];
+        let mut region = Region { name: "tiny".into(), polygons: vec![Polygon { vertices }], ..Default::default() };
+        let report = simplify_region(&mut region, 1000.0);
+
+        assert_eq!(report.vertices_after, 4);
+    }
+
+    #[test]
+    fn test_shared_edge_stays_nested() {
+        // The child shares the parent's southern edge, with a ~22 m bump where its ring starts
+        let ring = |vertices: &[(f64, f64)]| Polygon { vertices: vertices.iter().map(|&(lon, lat)| coords(lon, lat)).collect() };
+        let mut regions = vec![
+            Region {
+                name: "parent".into(),
+                polygons: vec![ring(&[(0.0, 0.0), (0.3, 0.0), (0.5, -0.0002), (0.7, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.0, 0.0)])],
+                ..Default::default()
+            },
+            Region {
+                name: "child".into(),
+                polygons: vec![ring(&[(0.5, -0.0002), (0.7, 0.0), (0.7, 0.5), (0.3, 0.5), (0.3, 0.0), (0.5, -0.0002)])],
+                parent: Some("parent".into()),
+                ..Default::default()
+            },
+        ];
+        assert!(crate::hierarchy::RegionHierarchy::new(&regions).is_ok());
+        simplify_regions(&mut regions, 50.0);
+
+        assert!(regions[0].polygons[0].vertices.contains(&coords(0.5, -0.0002)));
+        assert!(crate::hierarchy::RegionHierarchy::new(&regions).is_ok());
+    }
+}
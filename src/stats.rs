@@ -0,0 +1,199 @@
+use geo::GeodesicArea;
+use serde::Serialize;
+
+use crate::{compile_regions, data_structures::{Coordinates, DecimalLat, DecimalLon, Location, Region}, region_union, unwrap_longitude, wrap_longitude};
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct RegionStats {
+    pub region: String,
+    pub matched_count: usize,
+    /// Fraction of all the locations which matched the region
+    pub share: f64,
+    /// Centroid of the matched locations, none if nothing matched
+    pub centroid: Option<Coordinates>,
+    /// Matched locations per square kilometre of the region's geodesic area
+    pub density: Option<f64>,
+    /// Aggregated weights of the matched locations, none if none of them carries a weight
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weights: Option<WeightStats>,
+}
+
+#[derive(Serialize, Debug, PartialEq, Clone)]
+pub struct WeightStats {
+    /// Number of matched locations carrying a weight
+    pub count: usize,
+    pub sum: f64,
+    pub mean: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl WeightStats {
+    fn add(stats: Option<WeightStats>, weight: f64) -> WeightStats {
+        match stats {
+            None => WeightStats { count: 1, sum: weight, mean: weight, min: weight, max: weight },
+            Some(stats) => WeightStats {
+                count: stats.count + 1,
+                sum: stats.sum + weight,
+                mean: (stats.sum + weight) / (stats.count + 1) as f64,
+                min: stats.min.min(weight),
+                max: stats.max.max(weight),
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct MatchStats {
+    pub total_locations: usize,
+    pub matched: usize,
+    pub unmatched: usize,
+    /// Locations which matched more than one region
+    pub multi_matched: usize,
+    pub regions: Vec<RegionStats>,
+}
+
+/// Same matching as [`crate::match_locations_to_regions`], but only the counts are kept, without collecting matched locations.
+pub fn match_stats(locations: &[Location], regions: &[Region]) -> MatchStats {
+    let compiled = compile_regions(regions);
+    let mut counts = vec![0usize; regions.len()];
+    // Longitudes are summed unwrapped relative to the first matched location, so locations on both sides of the antimeridian stay together
+    let mut first_lons: Vec<Option<f64>> = vec![None; regions.len()];
+    let mut coord_sums = vec![(0.0, 0.0); regions.len()];
+    let mut weights: Vec<Option<WeightStats>> = vec![None; regions.len()];
+    let (mut matched, mut multi_matched) = (0, 0);
+
+    for loc in locations {
+        let mut matched_regions = 0;
+        for (idx, region) in compiled.iter().enumerate() {
+            if region.matches(loc) {
+                matched_regions += 1;
+                counts[idx] += 1;
+                let first_lon = *first_lons[idx].get_or_insert(loc.coordinates.longitude.val());
+                coord_sums[idx].0 += unwrap_longitude(loc.coordinates.longitude.val(), first_lon);
+                coord_sums[idx].1 += loc.coordinates.latitude.val();
+                if let Some(weight) = loc.weight {
+                    weights[idx] = Some(WeightStats::add(weights[idx].take(), weight));
+                }
+            }
+        }
+        if matched_regions > 0 {
+            matched += 1;
+        }
+        if matched_regions > 1 {
+            multi_matched += 1;
+        }
+    }
+
+    MatchStats {
+        total_locations: locations.len(),
+        matched,
+        unmatched: locations.len() - matched,
+        multi_matched,
+        regions: regions.iter().zip(counts).zip(coord_sums).zip(weights).map(
+            |(((region, count), (lon_sum, lat_sum)), weights)| {
+                // Same area as in describe, overlapping polygons of the region count once
+                let area_km2 = region_union(region).geodesic_area_unsigned() / 1_000_000.0;
+                RegionStats {
+                    region: region.name.clone(),
+                    matched_count: count,
+                    share: if locations.is_empty() { 0.0 } else { count as f64 / locations.len() as f64 },
+                    centroid: (count > 0).then(|| Coordinates {
+                        longitude: DecimalLon::new(wrap_longitude(lon_sum / count as f64)).unwrap(),
+                        latitude: DecimalLat::new(lat_sum / count as f64).unwrap(),
+                    }),
+                    density: (area_km2 > 0.0).then(|| count as f64 / area_km2),
+                    weights,
+                }
+            }
+        ).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{coords, rectangle, region};
+
+    use super::*;
+
+    fn square(name: &str, min_lon: f64, max_lon: f64) -> Region {
+        region(name, rectangle(min_lon, 0.0, max_lon, 1.0))
+    }
+
+    #[test]
+    fn test_match_stats() {
+        let regions = vec![square("big", 0.0, 2.0), square("small", 0.0, 1.0), square("empty", 10.0, 11.0)];
+        let locations = vec![
+            Location { name: "Location 1".into(), coordinates: coords(0.5, 0.2), ..Default::default() },
+            Location { name: "Location 2".into(), coordinates: coords(1.5, 0.4), ..Default::default() },
+            Location { name: "Location 3".into(), coordinates: coords(0.5, 0.6), ..Default::default() },
+            Location { name: "Location 4".into(), coordinates: coords(5.0, 0.5), ..Default::default() },
+        ];
+        let stats = match_stats(&locations, &regions);
+
+        assert_eq!(stats.total_locations, 4);
+        assert_eq!(stats.matched, 3);
+        assert_eq!(stats.unmatched, 1);
+        assert_eq!(stats.multi_matched, 2);
+
+        assert_eq!(stats.regions[0].matched_count, 3);
+        assert_eq!(stats.regions[0].share, 0.75);
+        assert_eq!(stats.regions[1].centroid, Some(coords(0.5, 0.4)));
+        // One square degree on the equator is ~12 308 km²
+        let density = stats.regions[1].density.unwrap();
+        assert!((density - 2.0 / 12_308.0).abs() < 1e-6, "{}", density);
+
+        assert_eq!(stats.regions[2].matched_count, 0);
+        assert_eq!(stats.regions[2].centroid, None);
+        assert_eq!(stats.regions[2].density, Some(0.0));
+    }
+
+    #[test]
+    fn test_weighted_stats() {
+        let regions = vec![square("big", 0.0, 2.0), square("small", 0.0, 1.0)];
+        let locations = vec![
+            Location { name: "Location 1".into(), coordinates: coords(0.5, 0.2), weight: Some(10.0), ..Default::default() },
+            Location { name: "Location 2".into(), coordinates: coords(1.5, 0.4), weight: Some(4.0), ..Default::default() },
+            Location { name: "Location 3".into(), coordinates: coords(1.5, 0.6), weight: None, ..Default::default() },
+        ];
+        let stats = match_stats(&locations, &regions);
+
+        assert_eq!(stats.regions[0].weights, Some(WeightStats { count: 2, sum: 14.0, mean: 7.0, min: 4.0, max: 10.0 }));
+        assert_eq!(stats.regions[1].weights, Some(WeightStats { count: 1, sum: 10.0, mean: 10.0, min: 10.0, max: 10.0 }));
+
+        let unweighted = match_stats(&locations[2..], &regions);
+        assert_eq!(unweighted.regions[0].weights, None);
+    }
+
+    #[test]
+    fn test_clockwise_region_density() {
+        let mut clockwise = square("clockwise", 0.0, 1.0);
+        clockwise.polygons[0].vertices.reverse();
+        let locations = vec![
+            Location { name: "Location 1".into(), coordinates: coords(0.5, 0.2), ..Default::default() },
+            Location { name: "Location 2".into(), coordinates: coords(0.5, 0.6), ..Default::default() },
+        ];
+        let density = match_stats(&locations, &[clockwise]).regions[0].density.unwrap();
+        assert!((density - 2.0 / 12_308.0).abs() < 1e-6, "{}", density);
+    }
+
+    #[test]
+    fn test_overlapping_polygons_density() {
+        let region = Region { name: "overlapping".into(), polygons: vec![rectangle(0.0, 0.0, 1.0, 1.0), rectangle(0.0, 0.0, 1.0, 1.0)], ..Default::default() };
+        let locations = vec![Location { name: "Location 1".into(), coordinates: coords(0.5, 0.2), ..Default::default() }];
+        let density = match_stats(&locations, &[region]).regions[0].density.unwrap();
+        assert!((density - 1.0 / 12_308.0).abs() < 1e-6, "{}", density);
+    }
+
+    #[test]
+    fn test_centroid_across_dateline() {
+        let regions = vec![Region { name: "dateline".into(), polygons: vec![rectangle(179.0, 0.0, 180.0, 1.0), rectangle(-180.0, 0.0, -179.0, 1.0)], ..Default::default() }];
+        let locations = vec![
+            Location { name: "Location 1".into(), coordinates: coords(179.9, 0.2), ..Default::default() },
+            Location { name: "Location 2".into(), coordinates: coords(-179.9, 0.6), ..Default::default() },
+        ];
+        let centroid = match_stats(&locations, &regions).regions[0].centroid.clone().unwrap();
+        assert!((centroid.longitude.val().abs() - 180.0).abs() < 1e-9, "{:?}", centroid);
+        assert!((centroid.latitude.val() - 0.4).abs() < 1e-9, "{:?}", centroid);
+    }
+}
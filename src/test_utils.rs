@@ -0,0 +1,23 @@
+//! Fixtures shared by the unit tests of the modules.
+
+use crate::data_structures::{Coordinates, DecimalLat, DecimalLon, Polygon, Region};
+
+pub(crate) fn coords(longitude: f64, latitude: f64) -> Coordinates {
+    Coordinates { longitude: DecimalLon::new(longitude).unwrap(), latitude: DecimalLat::new(latitude).unwrap() }
+}
+
+/// Closed counter-clockwise ring between the corners (min_lon, min_lat) and (max_lon, max_lat)
+pub(crate) fn rectangle(min_lon: f64, min_lat: f64, max_lon: f64, max_lat: f64) -> Polygon {
+    Polygon {
+        vertices: vec![coords(min_lon, min_lat), coords(max_lon, min_lat), coords(max_lon, max_lat), coords(min_lon, max_lat), coords(min_lon, min_lat)],
+    }
+}
+
+/// Square spanning from `min` to `max` in both longitude and latitude
+pub(crate) fn square(min: f64, max: f64) -> Polygon {
+    rectangle(min, min, max, max)
+}
+
+pub(crate) fn region(name: &str, polygon: Polygon) -> Region {
+    Region { name: name.into(), polygons: vec![polygon], ..Default::default() }
+}
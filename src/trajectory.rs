@@ -0,0 +1,449 @@
+use std::{collections::HashMap, error::Error};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Serialize;
+
+use geo::{coord, line_intersection::{line_intersection, LineIntersection}, Line};
+
+use crate::{compile_regions, data_structures::{Coordinates, DecimalLat, DecimalLon, Location, Region}, unwrap_longitude, wrap_longitude, CompiledRegion};
+
+#[derive(Debug, PartialEq)]
+pub enum TrajectoryError {
+    MissingTimestamp(String),
+    BufferedRegion(String),
+}
+
+impl Error for TrajectoryError {}
+
+impl std::fmt::Display for TrajectoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TrajectoryError::MissingTimestamp(location) => write!(f, "Location {} has no timestamp, which is required with a dwell time", location),
+            TrajectoryError::BufferedRegion(region) => write!(f, "Region {} has a buffer, which crossings do not support", region),
+        }
+    }
+}
+
+/// Points of a single track, ordered by their timestamps.
+#[derive(Debug)]
+pub struct Track<'a> {
+    pub id: Option<String>,
+    /// Points together with their indices in the locations the track was built from
+    pub points: Vec<(usize, &'a Location)>,
+}
+
+/// Groups locations by their track id (in order of the first appearance of each track) and orders every track by timestamps.
+/// Locations without a track id form a track of their own. Locations with equal timestamps keep their order,
+/// and locations without a timestamp keep their positions in the track, timed locations are ordered around them.
+pub fn group_by_track(locations: &[Location]) -> Vec<Track<'_>> {
+    let mut tracks: Vec<Track> = vec![];
+    let mut positions: HashMap<Option<&str>, usize> = HashMap::new();
+    for (idx, loc) in locations.iter().enumerate() {
+        let position = *positions.entry(loc.track.as_deref()).or_insert_with(|| {
+            tracks.push(Track { id: loc.track.clone(), points: vec![] });
+            tracks.len() - 1
+        });
+        tracks[position].points.push((idx, loc));
+    }
+    for track in &mut tracks {
+        let mut timed: Vec<(usize, &Location)> = track.points.iter().copied().filter(|(_, loc)| loc.timestamp.is_some()).collect();
+        timed.sort_by_key(|(_, loc)| loc.timestamp);
+        for (point, timed_point) in track.points.iter_mut().filter(|(_, loc)| loc.timestamp.is_some()).zip(timed) {
+            *point = timed_point;
+        }
+    }
+    tracks
+}
+
+#[derive(Serialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    Enter,
+    Exit,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct GeofenceEvent {
+    pub track: Option<String>,
+    pub region: String,
+    pub event: EventKind,
+    pub time: Option<DateTime<Utc>>,
+    /// Index of the location, at which the event happened, in the locations file
+    pub index: usize,
+}
+
+fn lasted(start: &Location, end: &Location, dwell: Option<TimeDelta>) -> bool {
+    match (dwell, start.timestamp, end.timestamp) {
+        (Some(dwell), Some(start_time), Some(end_time)) => end_time - start_time >= dwell,
+        _ => true,
+    }
+}
+
+fn event(track: &Track, region: &CompiledRegion, entered: bool, index: usize, location: &Location) -> GeofenceEvent {
+    GeofenceEvent {
+        track: track.id.clone(),
+        region: region.region.name.clone(),
+        event: if entered { EventKind::Enter } else { EventKind::Exit },
+        time: location.timestamp,
+        index,
+    }
+}
+
+/// Detects, for every track, the points at which it enters or exits a region.
+/// With `dwell`, a change of membership only counts if it lasts at least that long (until the next change, or the last point of the track),
+/// which suppresses flapping near borders; the event is then reported at the point where the change started.
+/// A track starts with the membership of its first point, so starting inside a region is not an enter event.
+pub fn detect_events(locations: &[Location], regions: &[Region], dwell: Option<TimeDelta>) -> Result<Vec<GeofenceEvent>, TrajectoryError> {
+    if dwell.is_some() {
+        if let Some(loc) = locations.iter().find(|loc| loc.timestamp.is_none()) {
+            return Err(TrajectoryError::MissingTimestamp(loc.name.clone()));
+        }
+    }
+
+    let regions = compile_regions(regions);
+    let mut events = vec![];
+    for track in group_by_track(locations) {
+        // Membership confirmed so far and the first point of a pending change, for every region
+        let (_, first) = track.points[0];
+        let mut inside: Vec<bool> = regions.iter().map(|region| region.matches(first)).collect();
+        let mut pending: Vec<Option<(usize, usize, &Location)>> = vec![None; regions.len()];
+        let mut track_events = vec![];
+
+        for (position, (idx, loc)) in track.points.iter().enumerate() {
+            for (region_idx, region) in regions.iter().enumerate() {
+                let observed = region.matches(loc);
+                if let Some((start_position, start_idx, start)) = pending[region_idx] {
+                    // The pending change lasted at least until this point, even if the track has just gone back
+                    if lasted(start, loc, dwell) {
+                        inside[region_idx] = !inside[region_idx];
+                        pending[region_idx] = None;
+                        track_events.push((start_position, event(&track, region, inside[region_idx], start_idx, start)));
+                    } else if observed == inside[region_idx] {
+                        pending[region_idx] = None;
+                    }
+                }
+                if observed != inside[region_idx] && pending[region_idx].is_none() {
+                    if lasted(loc, loc, dwell) {
+                        inside[region_idx] = observed;
+                        track_events.push((position, event(&track, region, observed, *idx, loc)));
+                    } else {
+                        pending[region_idx] = Some((position, *idx, *loc));
+                    }
+                }
+            }
+        }
+        // With dwell time, events are confirmed later than they happened, so they have to be put back in order
+        track_events.sort_by_key(|(position, _)| *position);
+        events.extend(track_events.into_iter().map(|(_, event)| event));
+    }
+    Ok(events)
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Crossing {
+    pub track: Option<String>,
+    pub region: String,
+    /// Indices, in the locations file, of the points the crossing segment goes between
+    pub from_index: usize,
+    pub to_index: usize,
+    /// Estimated coordinates where the track entered the region
+    pub entry: Coordinates,
+    /// Estimated coordinates where the track left the region
+    pub exit: Coordinates,
+}
+
+// Positions (as fractions of the segment length) at which the segment intersects the region's boundary
+fn boundary_intersections(segment: &Line, region: &CompiledRegion) -> Vec<f64> {
+    let delta = segment.delta();
+    let length_squared = delta.x * delta.x + delta.y * delta.y;
+    let position = |point: geo::Coord| ((point.x - segment.start.x) * delta.x + (point.y - segment.start.y) * delta.y) / length_squared;
+    region.geometry.iter()
+        .flat_map(|poly| std::iter::once(poly.exterior()).chain(poly.interiors()))
+        .flat_map(|ring| ring.lines())
+        .flat_map(|edge| match line_intersection(*segment, edge) {
+            Some(LineIntersection::SinglePoint { intersection, .. }) => vec![position(intersection)],
+            Some(LineIntersection::Collinear { intersection }) => vec![position(intersection.start), position(intersection.end)],
+            None => vec![],
+        })
+        .collect()
+}
+
+/// Finds regions which a track went through between two consecutive points, without any of its points landing inside them.
+/// Every separate pass through a region is a crossing of its own, merely touching the boundary is not a crossing.
+/// The track is assumed to go straight (in longitude/latitude, the same as regions' edges) between its points,
+/// the shorter way around, so a segment between longitudes 179 and -179 crosses the antimeridian.
+/// Buffers are not supported, a region with a buffer is an error.
+pub fn detect_crossings(locations: &[Location], regions: &[Region]) -> Result<Vec<Crossing>, TrajectoryError> {
+    if let Some(region) = regions.iter().find(|region| region.buffer.is_some()) {
+        return Err(TrajectoryError::BufferedRegion(region.name.clone()));
+    }
+
+    let regions = compile_regions(regions);
+    let mut crossings = vec![];
+    for track in group_by_track(locations) {
+        for pair in track.points.windows(2) {
+            let ((from_idx, from), (to_idx, to)) = (pair[0], pair[1]);
+            let start = coord! { x: from.coordinates.longitude.val(), y: from.coordinates.latitude.val() };
+            let end = coord! { x: unwrap_longitude(to.coordinates.longitude.val(), start.x), y: to.coordinates.latitude.val() };
+            if start == end {
+                continue;
+            }
+            // A segment crossing the antimeridian is also checked shifted by a whole turn, against regions on its other side
+            let shifts = match end.x {
+                x if x > 180.0 => vec![0.0, -360.0],
+                x if x < -180.0 => vec![0.0, 360.0],
+                _ => vec![0.0],
+            };
+            let segments: Vec<Line> = shifts.iter()
+                .map(|shift| Line::new(coord! { x: start.x + shift, y: start.y }, coord! { x: end.x + shift, y: end.y }))
+                .collect();
+            let at = |position: f64| Coordinates {
+                longitude: DecimalLon::new(wrap_longitude(start.x + position * (end.x - start.x))).unwrap(),
+                latitude: DecimalLat::new(start.y + position * (end.y - start.y)).unwrap(),
+            };
+            for region in &regions {
+                let active = region.region.is_active_at(from.timestamp.as_ref()) && region.region.is_active_at(to.timestamp.as_ref());
+                if !active || region.matches(from) || region.matches(to) {
+                    continue;
+                }
+                let mut positions: Vec<f64> = segments.iter().flat_map(|segment| boundary_intersections(segment, region)).collect();
+                positions.sort_by(f64::total_cmp);
+                positions.dedup_by(|next, previous| *next - *previous < 1e-12);
+
+                // Parts of the segment between consecutive intersections lie either inside or outside the region (touching the boundary
+                // leaves no part inside), consecutive parts inside form a single pass through the region
+                let mut passes: Vec<(f64, f64)> = vec![];
+                let mut previous_inside = false;
+                for part in positions.windows(2) {
+                    let inside = region.contains(&at((part[0] + part[1]) / 2.0));
+                    match (inside, previous_inside, passes.last_mut()) {
+                        (true, true, Some((_, exit))) => *exit = part[1],
+                        (true, _, _) => passes.push((part[0], part[1])),
+                        (false, _, _) => (),
+                    }
+                    previous_inside = inside;
+                }
+                crossings.extend(passes.into_iter().map(|(entry, exit)| Crossing {
+                    track: track.id.clone(),
+                    region: region.region.name.clone(),
+                    from_index: from_idx,
+                    to_index: to_idx,
+                    entry: at(entry),
+                    exit: at(exit),
+                }));
+            }
+        }
+    }
+    Ok(crossings)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{data_structures::Polygon, test_utils::{coords, rectangle, region}};
+
+    use super::*;
+
+    fn ping(track: &str, minute: u32, longitude: f64) -> Location {
+        Location {
+            name: format!("{}@{}", track, minute),
+            coordinates: coords(longitude, 0.5),
+            timestamp: Some(format!("2024-07-01T08:{:02}:00Z", minute).parse().unwrap()),
+            track: Some(track.into()),
+            ..Default::default()
+        }
+    }
+
+    fn regions() -> Vec<Region> {
+        vec![region("depot", rectangle(0.0, 0.0, 1.0, 1.0))]
+    }
+
+    #[test]
+    fn test_group_by_track() {
+        let locations = vec![ping("a", 5, 0.0), ping("b", 1, 0.0), ping("a", 1, 0.0)];
+        let tracks = group_by_track(&locations);
+
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(tracks[0].id, Some("a".into()));
+        assert_eq!(tracks[0].points.iter().map(|(idx, _)| *idx).collect::<Vec<_>>(), vec![2, 0]);
+        assert_eq!(tracks[1].points.iter().map(|(idx, _)| *idx).collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn test_untimed_points_keep_their_positions() {
+        let untimed = Location { timestamp: None, ..ping("a", 0, 0.0) };
+        let locations = vec![ping("a", 5, 0.0), untimed, ping("a", 1, 0.0), ping("a", 3, 0.0)];
+        let tracks = group_by_track(&locations);
+
+        assert_eq!(tracks[0].points.iter().map(|(idx, _)| *idx).collect::<Vec<_>>(), vec![2, 1, 3, 0]);
+    }
+
+    #[test]
+    fn test_enter_and_exit_events() {
+        let locations = vec![
+            ping("a", 0, 2.0),
+            ping("a", 1, 0.5),
+            ping("b", 0, 0.5),
+            ping("a", 2, 2.0),
+        ];
+        let events = detect_events(&locations, &regions(), None).unwrap();
+
+        let summary: Vec<_> = events.iter().map(|event| (event.track.as_deref().unwrap(), event.event, event.index)).collect();
+        assert_eq!(summary, vec![("a", EventKind::Enter, 1), ("a", EventKind::Exit, 3)]);
+    }
+
+    #[test]
+    fn test_track_starting_inside() {
+        let locations = vec![ping("a", 0, 0.5), ping("a", 1, 0.6), ping("a", 2, 2.0)];
+        let events = detect_events(&locations, &regions(), None).unwrap();
+
+        let summary: Vec<_> = events.iter().map(|event| (event.event, event.index)).collect();
+        assert_eq!(summary, vec![(EventKind::Exit, 2)]);
+
+        let events = detect_events(&locations, &regions(), Some(TimeDelta::minutes(5))).unwrap();
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn test_dwell_time_suppresses_flapping() {
+        let locations = vec![
+            ping("a", 0, 1.1),
+            ping("a", 1, 0.9), // inside for only a minute
+            ping("a", 2, 1.1),
+            ping("a", 3, 0.9),
+            ping("a", 5, 0.8),
+            ping("a", 9, 0.5),
+        ];
+        let events = detect_events(&locations, &regions(), Some(TimeDelta::minutes(5))).unwrap();
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, EventKind::Enter);
+        assert_eq!(events[0].index, 3);
+        assert_eq!(events[0].time, locations[3].timestamp);
+    }
+
+    #[test]
+    fn test_dwell_time_requires_timestamps() {
+        let locations = vec![Location { name: "untimed".into(), ..Default::default() }];
+        assert_eq!(detect_events(&locations, &regions(), Some(TimeDelta::minutes(5))).unwrap_err(), TrajectoryError::MissingTimestamp("untimed".into()));
+    }
+
+    #[test]
+    fn test_dwell_time_counts_until_next_change() {
+        let locations = vec![
+            ping("a", 0, 1.5),
+            ping("a", 10, 0.5),
+            ping("a", 20, 1.5),
+        ];
+        let events = detect_events(&locations, &regions(), Some(TimeDelta::minutes(5))).unwrap();
+
+        // The exit at the last point has not lasted long enough to be reported
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, EventKind::Enter);
+        assert_eq!(events[0].index, 1);
+    }
+
+    #[test]
+    fn test_crossing_between_points() {
+        let locations = vec![
+            ping("a", 0, -1.0),
+            ping("a", 1, 2.0),
+            ping("a", 2, 0.5), // lands inside, no crossing reported
+            ping("a", 3, 3.0),
+        ];
+        let crossings = detect_crossings(&locations, &regions()).unwrap();
+
+        assert_eq!(crossings, vec![
+            Crossing {
+                track: Some("a".into()),
+                region: "depot".into(),
+                from_index: 0,
+                to_index: 1,
+                entry: coords(0.0, 0.5),
+                exit: coords(1.0, 0.5),
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_segment_missing_region() {
+        let locations = vec![
+            Location { coordinates: coords(-1.0, 2.0), ..ping("a", 0, 0.0) },
+            Location { coordinates: coords(2.0, 2.0), ..ping("a", 1, 0.0) },
+        ];
+        assert!(detect_crossings(&locations, &regions()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_segment_touching_region() {
+        // Touches the corner of the depot only
+        let corner = vec![
+            Location { coordinates: coords(-1.0, 0.0), ..ping("a", 0, 0.0) },
+            Location { coordinates: coords(1.0, 2.0), ..ping("a", 1, 0.0) },
+        ];
+        assert!(detect_crossings(&corner, &regions()).unwrap().is_empty());
+
+        // Goes along the edge of the depot
+        let edge = vec![
+            Location { coordinates: coords(-1.0, 1.0), ..ping("a", 0, 0.0) },
+            Location { coordinates: coords(2.0, 1.0), ..ping("a", 1, 0.0) },
+        ];
+        assert!(detect_crossings(&edge, &regions()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_passes_through_concave_region() {
+        let u_shaped = Region {
+            name: "u".into(),
+            polygons: vec![
+                Polygon {
+                    vertices: [(0.0, 0.0), (3.0, 0.0), (3.0, 3.0), (2.0, 3.0), (2.0, 1.0), (1.0, 1.0), (1.0, 3.0), (0.0, 3.0), (0.0, 0.0)].iter()
+                        .map(|&(lon, lat)| coords(lon, lat))
+                        .collect(),
+                },
+            ],
+            ..Default::default()
+        };
+        let locations = vec![
+            Location { coordinates: coords(-1.0, 2.0), ..ping("a", 0, 0.0) },
+            Location { coordinates: coords(4.0, 2.0), ..ping("a", 1, 0.0) },
+        ];
+        let passes: Vec<_> = detect_crossings(&locations, &[u_shaped]).unwrap().into_iter().map(|crossing| (crossing.entry, crossing.exit)).collect();
+
+        assert_eq!(passes, vec![(coords(0.0, 2.0), coords(1.0, 2.0)), (coords(2.0, 2.0), coords(3.0, 2.0))]);
+    }
+
+    #[test]
+    fn test_crossing_requires_active_region() {
+        let mut regions = regions();
+        regions[0].valid_to = Some("2024-07-01T08:01:00Z".parse().unwrap());
+        let locations = vec![ping("a", 0, -1.0), ping("a", 1, 2.0)];
+        assert!(detect_crossings(&locations, &regions).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_crossing_across_antimeridian() {
+        let dateline = Region {
+            name: "dateline".into(),
+            polygons: vec![
+                rectangle(179.0, 0.0, 180.0, 1.0),
+                rectangle(-180.0, 0.0, -179.0, 1.0),
+            ],
+            ..Default::default()
+        };
+        let locations = vec![ping("a", 0, 178.0), ping("a", 1, -178.0)];
+        let crossings = detect_crossings(&locations, &[dateline]).unwrap();
+
+        assert_eq!(crossings.len(), 1);
+        assert_eq!((crossings[0].entry.clone(), crossings[0].exit.clone()), (coords(179.0, 0.5), coords(-179.0, 0.5)));
+        // Going the shorter way, the track does not cross the depot on the other side of the Earth
+        assert!(detect_crossings(&locations, &regions()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_buffered_region_is_rejected() {
+        let mut regions = regions();
+        regions[0].buffer = Some(100.0);
+        let locations = vec![ping("a", 0, -1.0), ping("a", 1, 2.0)];
+        assert_eq!(detect_crossings(&locations, &regions).unwrap_err(), TrajectoryError::BufferedRegion("depot".into()));
+    }
+}
@@ -0,0 +1,110 @@
+use std::{collections::HashSet, error::Error};
+
+use crate::{data_structures::Region, hierarchy::{HierarchyError, RegionHierarchy}, hole_within_polygons};
+
+#[derive(Debug, PartialEq)]
+pub enum ValidationError {
+    DuplicateRegion(String),
+    EmptyRegion(String),
+    /// Index of the hole which does not lie within any polygon of the region (it has no effect)
+    HoleOutsidePolygons { region: String, hole: usize },
+    InvalidTimeWindow(String),
+    InvalidBuffer(String),
+    Hierarchy(HierarchyError),
+}
+
+impl Error for ValidationError {}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ValidationError::DuplicateRegion(region) => write!(f, "Region {} is defined more than once", region),
+            ValidationError::EmptyRegion(region) => write!(f, "Region {} has no polygons", region),
+            ValidationError::HoleOutsidePolygons { region, hole } => write!(f, "Hole {} of region {} does not lie within any of its polygons", hole, region),
+            ValidationError::InvalidTimeWindow(region) => write!(f, "Region {} becomes valid only after it stops being valid", region),
+            ValidationError::InvalidBuffer(region) => write!(f, "Region {} has a buffer which is not a finite number", region),
+            ValidationError::Hierarchy(e) => write!(f, "Invalid region hierarchy! {}", e),
+        }
+    }
+}
+
+/// Checks the regions for problems which parsing does not catch, all the problems found are returned.
+/// The hierarchy is only checked when some region declares a parent.
+pub fn validate_regions(regions: &[Region]) -> Vec<ValidationError> {
+    let mut errors = vec![];
+    let mut names = HashSet::new();
+    for region in regions {
+        if !names.insert(region.name.as_str()) {
+            errors.push(ValidationError::DuplicateRegion(region.name.clone()));
+        }
+        if region.polygons.is_empty() {
+            errors.push(ValidationError::EmptyRegion(region.name.clone()));
+        }
+        // Regions read from files are already checked, but not regions built otherwise
+        for (idx, hole) in region.holes.iter().enumerate() {
+            if !hole_within_polygons(hole, &region.polygons) {
+                errors.push(ValidationError::HoleOutsidePolygons { region: region.name.clone(), hole: idx });
+            }
+        }
+        if let (Some(from), Some(to)) = (region.valid_from, region.valid_to) {
+            if from >= to {
+                errors.push(ValidationError::InvalidTimeWindow(region.name.clone()));
+            }
+        }
+        if region.buffer.is_some_and(|buffer| !buffer.is_finite()) {
+            errors.push(ValidationError::InvalidBuffer(region.name.clone()));
+        }
+    }
+
+    // Duplicates are already reported, the hierarchy would only repeat them
+    if names.len() == regions.len() && regions.iter().any(|region| region.parent.is_some()) {
+        if let Err(e) = RegionHierarchy::new(regions) {
+            errors.push(ValidationError::Hierarchy(e));
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::square;
+
+    use super::*;
+
+    #[test]
+    fn test_valid_regions() {
+        let regions = vec![
+            Region { name: "country".into(), polygons: vec![square(0.0, 3.0)], holes: vec![square(1.0, 2.0)], ..Default::default() },
+            Region { name: "city".into(), polygons: vec![square(0.0, 1.0)], parent: Some("country".into()), ..Default::default() },
+        ];
+        assert_eq!(validate_regions(&regions), vec![]);
+    }
+
+    #[test]
+    fn test_invalid_regions() {
+        let regions = vec![
+            Region { name: "a".into(), polygons: vec![square(0.0, 1.0)], holes: vec![square(2.0, 3.0)], ..Default::default() },
+            Region { name: "a".into(), ..Default::default() },
+            Region {
+                name: "b".into(),
+                polygons: vec![square(0.0, 1.0)],
+                valid_from: Some("2024-07-02T00:00:00Z".parse().unwrap()),
+                valid_to: Some("2024-07-01T00:00:00Z".parse().unwrap()),
+                parent: Some("c".into()),
+                ..Default::default()
+            },
+        ];
+        assert_eq!(validate_regions(&regions), vec![
+            ValidationError::HoleOutsidePolygons { region: "a".into(), hole: 0 },
+            ValidationError::DuplicateRegion("a".into()),
+            ValidationError::EmptyRegion("a".into()),
+            ValidationError::InvalidTimeWindow("b".into()),
+        ]);
+
+        let orphan = vec![Region { name: "b".into(), polygons: vec![square(0.0, 1.0)], parent: Some("c".into()), ..Default::default() }];
+        assert_eq!(
+            validate_regions(&orphan),
+            vec![ValidationError::Hierarchy(HierarchyError::UnknownParent { region: "b".into(), parent: "c".into() })]
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use chrono::{DateTime, Utc};
+use wasm_bindgen::prelude::*;
+
+use crate::{data_structures::{Coordinates, DecimalLat, DecimalLon, Location}, index::RegionIndex};
+
+fn parse_time(at: Option<String>) -> Result<Option<DateTime<Utc>>, JsError> {
+    at.map(|at| at.parse::<DateTime<Utc>>().map_err(|e| JsError::new(&format!("Invalid time {}: {}", at, e))))
+        .transpose()
+}
+
+fn coordinates(lat: f64, lon: f64) -> Result<Coordinates, JsError> {
+    Ok(Coordinates {
+        latitude: DecimalLat::new(lat).map_err(|e| JsError::new(&e.to_string()))?,
+        longitude: DecimalLon::new(lon).map_err(|e| JsError::new(&e.to_string()))?,
+    })
+}
+
+/// Regions compiled into an index, built from JSON in the regions file format.
+#[wasm_bindgen(js_name = Regions)]
+pub struct WasmRegions {
+    index: RegionIndex,
+}
+
+/// Regions of a batch of points: regions of point `i` are `regions[offsets[i]..offsets[i + 1]]`.
+#[wasm_bindgen]
+pub struct Matches {
+    offsets: Vec<u32>,
+    regions: Vec<u32>,
+}
+
+#[wasm_bindgen]
+impl Matches {
+    #[wasm_bindgen(getter)]
+    pub fn offsets(&self) -> Vec<u32> {
+        self.offsets.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn regions(&self) -> Vec<u32> {
+        self.regions.clone()
+    }
+}
+
+#[wasm_bindgen(js_class = Regions)]
+impl WasmRegions {
+    #[wasm_bindgen(constructor)]
+    pub fn new(regions: &str) -> Result<WasmRegions, JsError> {
+        let index = RegionIndex::from_json(regions.as_bytes()).map_err(|e| JsError::new(&e.to_string()))?;
+        Ok(WasmRegions { index })
+    }
+
+    /// Names of the regions, region indices returned by `lookup` and `lookupMany` point into this array.
+    #[wasm_bindgen(getter)]
+    pub fn names(&self) -> Vec<String> {
+        self.index.regions().iter().map(|region| region.name.clone()).collect()
+    }
+
+    /// Indices of the regions containing the point, optionally only of regions active at the `at` time (RFC 3339).
+    pub fn lookup(&self, lat: f64, lon: f64, at: Option<String>) -> Result<Vec<u32>, JsError> {
+        let time = parse_time(at)?;
+        Ok(self.index.lookup_indices(&coordinates(lat, lon)?, time.as_ref()).into_iter().map(|idx| idx as u32).collect())
+    }
+
+    /// Same as `lookup` for every point of the typed arrays of latitudes and longitudes.
+    #[wasm_bindgen(js_name = lookupMany)]
+    pub fn lookup_many(&self, lats: &[f64], lons: &[f64], at: Option<String>) -> Result<Matches, JsError> {
+        if lats.len() != lons.len() {
+            return Err(JsError::new(&format!("{} latitudes and {} longitudes were given", lats.len(), lons.len())));
+        }
+        let time = parse_time(at)?;
+        let mut matches = Matches { offsets: vec![0], regions: vec![] };
+        for (&lat, &lon) in lats.iter().zip(lons) {
+            let found = self.index.lookup_indices(&coordinates(lat, lon)?, time.as_ref());
+            matches.regions.extend(found.into_iter().map(|idx| idx as u32));
+            matches.offsets.push(matches.regions.len() as u32);
+        }
+        Ok(matches)
+    }
+
+    /// Matches locations (JSON in the locations file format) like the match subcommand, optionally evaluating all of them
+    /// at the `at` time (like `--at`). Returns JSON in the output file format.
+    #[wasm_bindgen(js_name = matchLocations)]
+    pub fn match_locations(&self, locations: &str, at: Option<String>) -> Result<String, JsError> {
+        let mut locations: Vec<Location> = serde_json::from_str(locations).map_err(|e| JsError::new(&format!("Invalid locations! {}", e)))?;
+        if let Some(at) = parse_time(at)? {
+            locations.iter_mut().for_each(|loc| loc.timestamp = Some(at));
+        }
+        serde_json::to_string(&self.index.match_locations(&locations)).map_err(|e| JsError::new(&e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REGIONS: &str = r#"[
+        {"name": "big", "coordinates": [[[0, 0], [2, 0], [2, 2], [0, 2], [0, 0]]]},
+        {"name": "small", "coordinates": [[[0, 0], [1, 0], [1, 1], [0, 1], [0, 0]]], "valid_to": "2024-07-01T00:00:00Z"}
+    ]"#;
+
+    // Errors are only created on wasm32, where they become JavaScript errors
+    #[test]
+    fn test_regions() {
+        let regions = WasmRegions::new(REGIONS).ok().unwrap();
+        assert_eq!(regions.names(), vec!["big", "small"]);
+        assert_eq!(regions.lookup(0.5, 0.5, Some("2024-08-01T00:00:00Z".into())).ok().unwrap(), vec![0]);
+
+        let matches = regions.lookup_many(&[0.5, 1.5, 3.0], &[0.5, 1.5, 3.0], None).ok().unwrap();
+        assert_eq!((matches.offsets(), matches.regions()), (vec![0, 2, 3, 3], vec![0, 1, 0]));
+
+        let locations = r#"[{"name": "a", "coordinates": [0.5, 0.5]}, {"name": "b", "coordinates": [1.5, 1.5]}]"#;
+        assert_eq!(
+            regions.match_locations(locations, None).ok().unwrap(),
+            r#"[{"region":"big","matched_locations":["a","b"]},{"region":"small","matched_locations":["a"]}]"#
+        );
+    }
+}